@@ -0,0 +1,204 @@
+// Arena that grows and shrinks with the number of snakes in play, so a handful
+// of players doesn't get lost on a map sized for a hundred
+
+use crate::config::{ArenaConfig, GameConfig, MapConfig};
+use crate::constants::*;
+use crate::player::snake::Snake;
+
+/// Whether the edge of an arena that went from `old` to `new` this tick moved
+/// onto the snake's head, or past it: the head touches a side of `new` that
+/// moved inwards.
+pub fn edge_reached(snake: &Snake, old: &MapConfig, new: &MapConfig) -> bool {
+    let head = &snake.nodes[0];
+    let radius = SNAKE_INITIAL_SIZE / 2.0;
+    (new.left > old.left && head.x - radius <= new.left)
+        || (new.top > old.top && head.y - radius <= new.top)
+        || (new.right < old.right && head.x + radius >= new.right)
+        || (new.bottom < old.bottom && head.y + radius >= new.bottom)
+}
+
+pub struct ArenaScaler {
+    // Current fraction of the map width and height in use
+    scale: f64,
+}
+
+impl ArenaScaler {
+    pub fn new() -> ArenaScaler {
+        ArenaScaler { scale: 1.0 }
+    }
+
+    /// Move the scale one tick towards the size wanted for `snakes`. The area
+    /// follows the snake count, so the sides follow its square root.
+    pub fn update(&mut self, config: &ArenaConfig, snakes: usize) {
+        if !config.scaling {
+            self.scale = 1.0;
+            return;
+        }
+        let min_size = config.min_size.clamp(0.0, 1.0);
+        let target = (snakes as f64 / config.full_players.max(1) as f64)
+            .sqrt()
+            .clamp(min_size, 1.0);
+        let step = config.resize_speed * GAME_LOOP_DELAY as f64 / 1000.0;
+        self.scale += (target - self.scale).clamp(-step, step);
+    }
+
+    /// Shrink the map to the current arena and scale the bait targets with its area.
+    /// The arena never gets smaller than MAP_MIN_SIZE on either side.
+    pub fn apply(&self, config: &mut GameConfig) {
+        let floor = MAP_MIN_SIZE / config.map.width().min(config.map.height());
+        let scale = self.scale.max(floor);
+        if scale >= 1.0 {
+            return;
+        }
+        let area = scale * scale;
+        config.map = config.map.scaled(scale);
+        config.bait.max_baits = (config.bait.max_baits as f64 * area) as usize;
+        for region in &mut config.bait.regions {
+            region.target = (region.target as f64 * area) as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BaitRegion, SnakeConfig};
+    use crate::player::snake::find_spawn_point;
+
+    fn scaling() -> ArenaConfig {
+        ArenaConfig {
+            scaling: true,
+            ..ArenaConfig::default()
+        }
+    }
+
+    // Enough ticks to cross the whole range at the default resize speed
+    fn settle(scaler: &mut ArenaScaler, config: &ArenaConfig, snakes: usize) {
+        for _ in 0..1000 {
+            scaler.update(config, snakes);
+        }
+    }
+
+    #[test]
+    fn scaler_keeps_the_whole_map_when_off() {
+        let mut scaler = ArenaScaler::new();
+        settle(&mut scaler, &ArenaConfig::default(), 0);
+        let mut config = GameConfig::default();
+        scaler.apply(&mut config);
+        assert_eq!(config.map, GameConfig::default().map);
+        assert_eq!(config.bait.max_baits, GameConfig::default().bait.max_baits);
+    }
+
+    #[test]
+    fn scaler_resizes_by_at_most_resize_speed() {
+        let config = scaling();
+        let mut scaler = ArenaScaler::new();
+        scaler.update(&config, 0);
+        let step = config.resize_speed * GAME_LOOP_DELAY as f64 / 1000.0;
+        assert!((scaler.scale - (1.0 - step)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scaler_follows_the_square_root_of_the_snake_count() {
+        let config = scaling();
+        let mut scaler = ArenaScaler::new();
+        settle(&mut scaler, &config, 0);
+        assert!((scaler.scale - config.min_size).abs() < 1e-9);
+        settle(&mut scaler, &config, config.full_players / 4);
+        assert!((scaler.scale - 0.5).abs() < 1e-9);
+        settle(&mut scaler, &config, config.full_players * 2);
+        assert!((scaler.scale - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scaler_shrinks_the_map_and_bait_targets() {
+        let mut scaler = ArenaScaler::new();
+        settle(&mut scaler, &scaling(), scaling().full_players / 4);
+
+        let mut config = GameConfig::default();
+        config.bait.max_baits = 1000;
+        config.bait.regions.push(BaitRegion {
+            left: 0.0,
+            top: 0.0,
+            right: 100.0,
+            bottom: 100.0,
+            target: 40,
+        });
+        let map = config.map;
+        scaler.apply(&mut config);
+
+        assert!((config.map.width() - map.width() / 2.0).abs() < 1e-6);
+        assert!((config.map.height() - map.height() / 2.0).abs() < 1e-6);
+        assert_eq!(config.map.center(), map.center());
+        assert!((249..=250).contains(&config.bait.max_baits));
+        assert!((9..=10).contains(&config.bait.regions[0].target));
+    }
+
+    // One tick of a snake heading down along the left edge, as the game loop runs
+    // it. Returns whether it survived.
+    fn move_along_left_edge(snake: &mut Snake, last: &MapConfig, map: &MapConfig) -> bool {
+        let pushed = edge_reached(snake, last, map);
+        if pushed {
+            snake.push_inside(map);
+        }
+        snake.move_snake(640.0, 1000.0, 1280.0, 720.0, &SnakeConfig::default(), map);
+        pushed || !snake.head_touches_border(map)
+    }
+
+    #[test]
+    fn shrinking_edge_pushes_a_snake_moving_along_it() {
+        let config = scaling();
+        let mut scaler = ArenaScaler::new();
+        let mut map = GameConfig::default().map;
+        let (_, center_y) = map.center();
+        let mut snake = Snake::new_at(0, map.left + SNAKE_INITIAL_SIZE / 2.0 + 1.0, center_y);
+        snake.current_angle = 90.0;
+
+        // Two ticks of the edge moving onto the snake, then two with it standing still
+        for shrinking in [true, true, false, false] {
+            let last = map;
+            if shrinking {
+                scaler.update(&config, 0);
+            }
+            let mut game = GameConfig::default();
+            scaler.apply(&mut game);
+            map = game.map;
+            assert!(move_along_left_edge(&mut snake, &last, &map));
+            assert!(!snake.head_touches_border(&map));
+        }
+    }
+
+    #[test]
+    fn shrinking_edge_leaves_snakes_it_doesnt_reach() {
+        let old = GameConfig::default().map;
+        let new = old.scaled(0.99);
+        let (center_x, center_y) = old.center();
+        assert!(!edge_reached(
+            &Snake::new_at(0, center_x, center_y),
+            &old,
+            &new
+        ));
+        let near = Snake::new_at(0, old.left + 10.0, center_y);
+        assert!(edge_reached(&near, &old, &new));
+        assert!(!edge_reached(&near, &new, &old));
+    }
+
+    #[test]
+    fn scaler_keeps_the_arena_at_least_map_min_size() {
+        let config = ArenaConfig {
+            min_size: 0.0,
+            ..scaling()
+        };
+        let mut scaler = ArenaScaler::new();
+        settle(&mut scaler, &config, 0);
+        assert_eq!(scaler.scale, 0.0);
+
+        let mut game = GameConfig::default();
+        scaler.apply(&mut game);
+        assert!((game.map.width() - MAP_MIN_SIZE).abs() < 1e-6);
+        assert!((game.map.height() - MAP_MIN_SIZE).abs() < 1e-6);
+        // Snakes can still be placed in it
+        let (x, y) = find_spawn_point(std::iter::empty(), &game.map, &game.layout, 0.0);
+        assert!(game.map.contains(x, y));
+    }
+}
@@ -1,15 +1,137 @@
+pub mod spawn;
+
+use std::time::Instant;
+
+use crate::config::BaitConfig;
+use crate::constants::*;
+
+/// Temporary effect given to the snake that eats a power-up bait
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PowerUp {
+    // Moves faster
+    Speed,
+    // Pulls baits in from further away
+    Magnet,
+    // Passes through other snakes
+    Ghost,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 3] = [PowerUp::Speed, PowerUp::Magnet, PowerUp::Ghost];
+
+    pub fn code(&self) -> i32 {
+        match self {
+            PowerUp::Speed => 0,
+            PowerUp::Magnet => 1,
+            PowerUp::Ghost => 2,
+        }
+    }
+}
+
+/// Where a bait came from, which decides how long it lives and how clients draw it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BaitKind {
+    // Spawned by the server
+    Natural,
+    // Left behind by a dead snake
+    Corpse,
+    // Dropped while boosting
+    Trail,
+    PowerUp(PowerUp),
+}
+
+impl BaitKind {
+    // Sent as the last field of the "3," message
+    pub fn code(&self) -> i32 {
+        match self {
+            BaitKind::Natural => 0,
+            BaitKind::Corpse => 1,
+            BaitKind::Trail => 2,
+            BaitKind::PowerUp(power_up) => 3 + power_up.code(),
+        }
+    }
+}
+
+/// What happened to a bait as it aged this tick
+#[derive(PartialEq)]
+pub enum BaitDecay {
+    Fresh,
+    Shrunk,
+    Expired,
+}
+
 pub struct Bait {
     pub x: f64,
     pub y: f64,
     pub color: String,
     pub size: f64,
+    pub kind: BaitKind,
+    pub created: Instant,
+    pub initial_size: f64,
 }
 
 impl Bait {
-    pub fn new(x: f64, y: f64, color: String, size: f64) -> Bait {
-        let new_bait = Bait { x, y, color, size };
-        new_bait
+    pub fn new(x: f64, y: f64, color: String, size: f64, kind: BaitKind) -> Bait {
+        Bait {
+            x,
+            y,
+            color,
+            size,
+            kind,
+            created: Instant::now(),
+            initial_size: size,
+        }
+    }
+
+    /// Age the bait: once past `decay_start` of its lifetime it shrinks linearly
+    /// until it expires. A lifetime of 0 means it never expires.
+    pub fn decay(&mut self, config: &BaitConfig) -> BaitDecay {
+        let lifetime = config.lifetime(self.kind);
+        if lifetime <= 0.0 {
+            return BaitDecay::Fresh;
+        }
+
+        let age = self.created.elapsed().as_secs_f64() / lifetime;
+        if age >= 1.0 {
+            return BaitDecay::Expired;
+        }
+        if age <= config.decay_start {
+            return BaitDecay::Fresh;
+        }
+
+        let remaining = (1.0 - age) / (1.0 - config.decay_start);
+        self.size = self.initial_size * remaining;
+        BaitDecay::Shrunk
     }
+
+    /// Drift towards the closest snake head whose attraction radius reaches the bait,
+    /// moving at most `speed`. Heads are given as (x, y, radius). Returns whether the bait moved.
+    pub fn attract(&mut self, heads: &[(f64, f64, f64)], speed: f64) -> bool {
+        let closest = heads
+            .iter()
+            .map(|(x, y, radius)| (x - self.x, y - self.y, radius))
+            .map(|(dx, dy, radius)| (dx, dy, (dx * dx + dy * dy).sqrt(), radius))
+            .filter(|(_, _, dist, radius)| *dist > 0.0 && dist <= radius)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+
+        let Some((dx, dy, dist, _)) = closest else {
+            return false;
+        };
+        let step = speed.min(dist);
+        self.x += dx / dist * step;
+        self.y += dy / dist * step;
+        true
+    }
+}
+
+// Random "r,g,b" color as sent in the "3," message
+pub fn random_color() -> String {
+    format!(
+        "{},{},{}",
+        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
+        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
+        rand::random_range(0..MAX_BAIT_COLOR_RANGE)
+    )
 }
 
 impl Clone for Bait {
@@ -19,7 +141,76 @@ impl Clone for Bait {
             y: self.y,
             color: self.color.clone(),
             size: self.size,
+            kind: self.kind,
+            created: self.created,
+            initial_size: self.initial_size,
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // A corpse bait of size 10 that was dropped `age` seconds ago
+    fn corpse(age: f64) -> Bait {
+        let mut bait = Bait::new(0.0, 0.0, random_color(), 10.0, BaitKind::Corpse);
+        bait.created = Instant::now() - Duration::from_secs_f64(age);
+        bait
+    }
+
+    #[test]
+    fn decay_keeps_young_baits() {
+        let config = BaitConfig::default();
+        let mut bait = corpse(1.0);
+        assert!(bait.decay(&config) == BaitDecay::Fresh);
+        assert_eq!(bait.size, 10.0);
+    }
+
+    #[test]
+    fn decay_shrinks_after_decay_start() {
+        // Corpses live 30 seconds and start shrinking at 0.7 of that
+        let config = BaitConfig::default();
+        let mut bait = corpse(25.5);
+        assert!(bait.decay(&config) == BaitDecay::Shrunk);
+        assert!((bait.size - 5.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn decay_expires_at_end_of_lifetime() {
+        let config = BaitConfig::default();
+        assert!(corpse(30.5).decay(&config) == BaitDecay::Expired);
+    }
+
+    #[test]
+    fn decay_never_expires_with_zero_lifetime() {
+        let config = BaitConfig::default();
+        let mut bait = Bait::new(0.0, 0.0, random_color(), 10.0, BaitKind::Natural);
+        bait.created = Instant::now() - Duration::from_secs(100);
+        assert!(bait.decay(&config) == BaitDecay::Fresh);
+    }
+
+    #[test]
+    fn attract_moves_towards_closest_head_in_reach() {
+        let mut bait = Bait::new(0.0, 0.0, random_color(), 10.0, BaitKind::Natural);
+        let heads = [(30.0, 0.0, 40.0), (0.0, -20.0, 40.0)];
+        assert!(bait.attract(&heads, 5.0));
+        assert_eq!((bait.x, bait.y), (0.0, -5.0));
+    }
+
+    #[test]
+    fn attract_ignores_heads_out_of_reach() {
+        let mut bait = Bait::new(0.0, 0.0, random_color(), 10.0, BaitKind::Natural);
+        assert!(!bait.attract(&[(100.0, 0.0, 40.0)], 5.0));
+        assert_eq!((bait.x, bait.y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn attract_stops_at_the_head() {
+        let mut bait = Bait::new(0.0, 0.0, random_color(), 10.0, BaitKind::Natural);
+        assert!(bait.attract(&[(3.0, 4.0, 40.0)], 6.0));
+        assert_eq!((bait.x, bait.y), (3.0, 4.0));
+    }
+}
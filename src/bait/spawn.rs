@@ -0,0 +1,207 @@
+use crate::bait::{Bait, BaitKind, PowerUp, random_color};
+use crate::config::{BaitConfig, BaitPolicy, BaitRegion, MapConfig};
+use crate::map::MapLayout;
+
+// Keep spawned baits this far from the map border
+const SPAWN_MARGIN: f64 = 10.0;
+
+fn random_map_point(map: &MapConfig) -> (f64, f64) {
+    (
+        rand::random_range(map.left + SPAWN_MARGIN..map.right - SPAWN_MARGIN),
+        rand::random_range(map.top + SPAWN_MARGIN..map.bottom - SPAWN_MARGIN),
+    )
+}
+
+// The part of a region baits can spawn in on the map, None when there is none
+fn clip_region(region: &BaitRegion, map: &MapConfig) -> Option<BaitRegion> {
+    let clipped = BaitRegion {
+        left: region.left.max(map.left + SPAWN_MARGIN),
+        top: region.top.max(map.top + SPAWN_MARGIN),
+        right: region.right.min(map.right - SPAWN_MARGIN),
+        bottom: region.bottom.min(map.bottom - SPAWN_MARGIN),
+        target: region.target,
+    };
+    (clipped.right > clipped.left && clipped.bottom > clipped.top).then_some(clipped)
+}
+
+/// Decides where and how many baits appear each tick, following the configured policy
+pub struct BaitSpawner {
+    config: BaitConfig,
+    map: MapConfig,
+    layout: MapLayout,
+    patches: Vec<(f64, f64)>,
+    // Fractional baits carried over to the next tick
+    pending: f64,
+}
+
+impl BaitSpawner {
+    pub fn new(config: BaitConfig, map: MapConfig, layout: MapLayout) -> BaitSpawner {
+        let patches = (0..config.patch_count)
+            .map(|_| random_map_point(&map))
+            .collect();
+        BaitSpawner {
+            config,
+            map,
+            layout,
+            patches,
+            pending: 0.0,
+        }
+    }
+
+    /// Follow an arena that grew or shrank. Food patches it left behind move to
+    /// a new spot inside, or their food would pile up along its edge.
+    pub fn resize(&mut self, config: BaitConfig, map: MapConfig) {
+        self.config = config;
+        self.map = map;
+        for patch in &mut self.patches {
+            if !map.contains(patch.0, patch.1) {
+                *patch = random_map_point(&map);
+            }
+        }
+    }
+
+    /// New baits for this tick, given the baits already on the map and how many snakes are in play
+    pub fn spawn(&mut self, baits: &[Bait], active_players: usize) -> Vec<Bait> {
+        self.pending +=
+            self.config.refill_per_tick + self.config.refill_per_player * active_players as f64;
+        let count =
+            (self.pending.floor() as usize).min(self.config.max_baits.saturating_sub(baits.len()));
+        self.pending = self.pending.fract();
+
+        let mut new_baits: Vec<Bait> = Vec::new();
+        for _ in 0..count {
+            let point = match self.config.policy {
+                BaitPolicy::Uniform => Some(random_map_point(&self.map)),
+                BaitPolicy::Regional => self.regional_point(baits, &new_baits),
+                BaitPolicy::Clustered => self.patch_point(),
+            };
+            let Some((x, y)) = point else {
+                break;
+            };
+            // Nothing grows inside obstacles, try again next tick
+            if self.layout.blocks(x, y, 0.0) {
+                continue;
+            }
+            if rand::random_bool(self.config.powerup_chance.clamp(0.0, 1.0)) {
+                let power_up = PowerUp::ALL[rand::random_range(0..PowerUp::ALL.len())];
+                new_baits.push(Bait::new(
+                    x,
+                    y,
+                    random_color(),
+                    self.config.powerup_size,
+                    BaitKind::PowerUp(power_up),
+                ));
+            } else {
+                let size = rand::random::<f64>() * self.config.max_size;
+                new_baits.push(Bait::new(x, y, random_color(), size, BaitKind::Natural));
+            }
+        }
+        new_baits
+    }
+
+    // Point in the region that is furthest below its target, if any is. Only the
+    // part of each region inside the map counts, so a shrunk arena doesn't get
+    // baits spawned outside it.
+    fn regional_point(&self, baits: &[Bait], new_baits: &[Bait]) -> Option<(f64, f64)> {
+        let region = self
+            .config
+            .regions
+            .iter()
+            .filter_map(|region| clip_region(region, &self.map))
+            .map(|region| {
+                let inside = baits
+                    .iter()
+                    .chain(new_baits)
+                    .filter(|b| {
+                        b.x >= region.left
+                            && b.x <= region.right
+                            && b.y >= region.top
+                            && b.y <= region.bottom
+                    })
+                    .count();
+                let missing = region.target as i64 - inside as i64;
+                (region, missing)
+            })
+            .filter(|(_, missing)| *missing > 0)
+            .max_by_key(|(_, missing)| *missing)?
+            .0;
+
+        Some((
+            rand::random_range(region.left..region.right),
+            rand::random_range(region.top..region.bottom),
+        ))
+    }
+
+    // Point scattered around one of the food patches
+    fn patch_point(&self) -> Option<(f64, f64)> {
+        if self.patches.is_empty() {
+            return None;
+        }
+        let (center_x, center_y) = self.patches[rand::random_range(0..self.patches.len())];
+        let angle = rand::random_range(0.0..std::f64::consts::TAU);
+        let dist = rand::random::<f64>().sqrt() * self.config.patch_radius;
+        Some((
+            (center_x + angle.cos() * dist)
+                .clamp(self.map.left + SPAWN_MARGIN, self.map.right - SPAWN_MARGIN),
+            (center_y + angle.sin() * dist)
+                .clamp(self.map.top + SPAWN_MARGIN, self.map.bottom - SPAWN_MARGIN),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(left: f64, right: f64) -> MapConfig {
+        MapConfig {
+            left,
+            top: left,
+            right,
+            bottom: right,
+        }
+    }
+
+    fn region(left: f64, right: f64, target: usize) -> BaitRegion {
+        BaitRegion {
+            left,
+            top: left,
+            right,
+            bottom: right,
+            target,
+        }
+    }
+
+    #[test]
+    fn resize_moves_patches_left_outside() {
+        let config = BaitConfig {
+            policy: BaitPolicy::Clustered,
+            patch_count: 50,
+            ..BaitConfig::default()
+        };
+        let mut spawner = BaitSpawner::new(config.clone(), map(0.0, 2000.0), MapLayout::default());
+        let arena = map(900.0, 1100.0);
+        spawner.resize(config, arena);
+        assert_eq!(spawner.patches.len(), 50);
+        assert!(spawner.patches.iter().all(|p| arena.contains(p.0, p.1)));
+    }
+
+    #[test]
+    fn regional_baits_spawn_inside_a_shrunk_arena() {
+        let config = BaitConfig {
+            policy: BaitPolicy::Regional,
+            refill_per_tick: 50.0,
+            powerup_chance: 0.0,
+            // The first region is left entirely outside the arena
+            regions: vec![region(0.0, 500.0, 1000), region(0.0, 2000.0, 100)],
+            ..BaitConfig::default()
+        };
+        let mut spawner = BaitSpawner::new(config.clone(), map(0.0, 2000.0), MapLayout::default());
+        let arena = map(900.0, 1100.0);
+        spawner.resize(config, arena);
+
+        let baits = spawner.spawn(&[], 0);
+        assert_eq!(baits.len(), 50);
+        assert!(baits.iter().all(|b| arena.contains(b.x, b.y)));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::bait::Bait;
+use crate::config::{GameConfig, MapConfig};
+use crate::constants::*;
+use crate::player::Player;
+
+/// What a bot does this tick. The direction is relative to the head, the same way
+/// a client's mouse position is relative to the center of its window.
+pub struct BotDecision {
+    pub dir_x: f64,
+    pub dir_y: f64,
+    pub accelerate: bool,
+}
+
+// Bots only boost when they can afford to lose a node
+fn can_boost(bot: &Player) -> bool {
+    bot.snake.nodes.len() > SNAKE_INITIAL_LENGTH
+}
+
+/// Steer away from enemy bodies, obstacles and the edge of `arena` first,
+/// otherwise go for the closest bait in sight inside it, otherwise keep
+/// wandering. The arena is the round's while one is played, else the map.
+pub fn think(
+    bot: &Player,
+    players: &HashMap<u128, Player>,
+    baits: &[Bait],
+    config: &GameConfig,
+    arena: &MapConfig,
+) -> BotDecision {
+    let head = &bot.snake.nodes[0];
+
+    // Every enemy node nearby pushes the bot away, closer nodes push harder
+    let mut avoid_x = 0.0;
+    let mut avoid_y = 0.0;
+    for other in players.values() {
+        if other.id == bot.id || !other.is_playing() {
+            continue;
+        }
+        for node in &other.snake.nodes {
+            let dx = head.x - node.x;
+            let dy = head.y - node.y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist > 0.0 && dist < BOT_DANGER_RADIUS {
+                let weight = (BOT_DANGER_RADIUS - dist) / dist;
+                avoid_x += dx * weight;
+                avoid_y += dy * weight;
+            }
+        }
+    }
+
+    for obstacle in &config.layout.obstacles {
+        let (x, y) = obstacle.closest_point(head.x, head.y);
+        let dx = head.x - x;
+        let dy = head.y - y;
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist > 0.0 && dist < BOT_BORDER_MARGIN {
+            avoid_x += dx / dist * BOT_BORDER_MARGIN;
+            avoid_y += dy / dist * BOT_BORDER_MARGIN;
+        }
+    }
+
+    if head.x - arena.left < BOT_BORDER_MARGIN {
+        avoid_x += BOT_BORDER_MARGIN;
+    }
+    if arena.right - head.x < BOT_BORDER_MARGIN {
+        avoid_x -= BOT_BORDER_MARGIN;
+    }
+    if head.y - arena.top < BOT_BORDER_MARGIN {
+        avoid_y += BOT_BORDER_MARGIN;
+    }
+    if arena.bottom - head.y < BOT_BORDER_MARGIN {
+        avoid_y -= BOT_BORDER_MARGIN;
+    }
+
+    if avoid_x != 0.0 || avoid_y != 0.0 {
+        return BotDecision {
+            dir_x: avoid_x,
+            dir_y: avoid_y,
+            accelerate: can_boost(bot) && rand::random_bool(BOT_BOOST_CHANCE * 5.0),
+        };
+    }
+
+    let nearest_bait = baits
+        .iter()
+        .filter(|bait| arena.contains(bait.x, bait.y))
+        .map(|bait| {
+            let dx = bait.x - head.x;
+            let dy = bait.y - head.y;
+            (bait, dx * dx + dy * dy)
+        })
+        .filter(|(_, dist_sq)| *dist_sq < BOT_SIGHT_RADIUS * BOT_SIGHT_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    if let Some((bait, _)) = nearest_bait {
+        return BotDecision {
+            dir_x: bait.x - head.x,
+            dir_y: bait.y - head.y,
+            accelerate: can_boost(bot) && rand::random_bool(BOT_BOOST_CHANCE),
+        };
+    }
+
+    let angle =
+        bot.move_y.atan2(bot.move_x) + rand::random_range(-BOT_WANDER_JITTER..BOT_WANDER_JITTER);
+    BotDecision {
+        dir_x: angle.cos(),
+        dir_y: angle.sin(),
+        accelerate: false,
+    }
+}
@@ -1,5 +1,6 @@
 // Helper module for collision detection
 
+#[derive(Clone, Copy, Debug)]
 pub struct Rect {
     pub top: f64,
     pub left: f64,
@@ -7,9 +8,22 @@ pub struct Rect {
     pub bottom: f64,
 }
 
+impl Rect {
+    // Rect centered on a point, reaching half_w / half_h to each side
+    pub fn around(x: f64, y: f64, half_w: f64, half_h: f64) -> Rect {
+        Rect {
+            top: y - half_h,
+            left: x - half_w,
+            right: x + half_w,
+            bottom: y + half_h,
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.left && x <= self.right && y >= self.top && y <= self.bottom
+    }
+}
+
 pub fn rect_intersect(r1: &Rect, r2: &Rect) -> bool {
-    !(r2.left > r1.right || 
-      r2.right < r1.left || 
-      r2.top > r1.bottom ||
-      r2.bottom < r1.top)
-} 
\ No newline at end of file
+    !(r2.left > r1.right || r2.right < r1.left || r2.top > r1.bottom || r2.bottom < r1.top)
+}
@@ -0,0 +1,794 @@
+// Runtime game configuration, loaded from a simple `key = value` file.
+// Anything missing from the file keeps its default value. A `[name]` line
+// starts a new room; settings above the first one apply to every room.
+
+use std::fs;
+
+use crate::bait::{BaitKind, PowerUp};
+use crate::constants::*;
+use crate::map::{MapFile, MapLayout};
+
+/// How new baits are placed on the map
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BaitPolicy {
+    // Anywhere on the map
+    Uniform,
+    // Inside the configured regions, topping up whichever is furthest below its target
+    Regional,
+    // Around a few fixed "food patch" centers
+    Clustered,
+}
+
+/// A rectangle of the map that should hold `target` baits
+#[derive(Clone, Debug)]
+pub struct BaitRegion {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub target: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct BaitConfig {
+    pub policy: BaitPolicy,
+    pub max_baits: usize,
+    pub max_size: f64,
+    // Baits added per tick, plus this much more for every snake in play
+    pub refill_per_tick: f64,
+    pub refill_per_player: f64,
+    pub regions: Vec<BaitRegion>,
+    pub patch_count: usize,
+    pub patch_radius: f64,
+    // Seconds each kind of bait lives, 0 for forever
+    pub natural_lifetime: f64,
+    pub corpse_lifetime: f64,
+    pub trail_lifetime: f64,
+    pub powerup_lifetime: f64,
+    // Fraction of its lifetime after which a bait starts shrinking
+    pub decay_start: f64,
+    // Baits this close to a snake head drift towards it at attraction_speed per tick
+    pub attraction_radius: f64,
+    pub attraction_speed: f64,
+    // Chance that a spawned bait is a power-up instead of plain food
+    pub powerup_chance: f64,
+    pub powerup_size: f64,
+}
+
+impl BaitConfig {
+    pub fn lifetime(&self, kind: BaitKind) -> f64 {
+        match kind {
+            BaitKind::Natural => self.natural_lifetime,
+            BaitKind::Corpse => self.corpse_lifetime,
+            BaitKind::Trail => self.trail_lifetime,
+            BaitKind::PowerUp(_) => self.powerup_lifetime,
+        }
+    }
+}
+
+impl Default for BaitConfig {
+    fn default() -> Self {
+        BaitConfig {
+            policy: BaitPolicy::Uniform,
+            max_baits: 1000,
+            max_size: 10.0,
+            refill_per_tick: 1.0,
+            refill_per_player: 0.0,
+            regions: Vec::new(),
+            patch_count: 6,
+            patch_radius: 150.0,
+            natural_lifetime: 0.0,
+            corpse_lifetime: 30.0,
+            trail_lifetime: 15.0,
+            powerup_lifetime: 20.0,
+            decay_start: 0.7,
+            attraction_radius: 40.0,
+            attraction_speed: 6.0,
+            powerup_chance: 0.01,
+            powerup_size: 12.0,
+        }
+    }
+}
+
+/// How long each power-up lasts (in seconds) and how strong it is
+#[derive(Clone, Debug)]
+pub struct PowerUpConfig {
+    pub speed_duration: f64,
+    pub speed_multiplier: f64,
+    pub magnet_duration: f64,
+    pub magnet_radius: f64,
+    pub ghost_duration: f64,
+}
+
+impl PowerUpConfig {
+    pub fn duration_ticks(&self, power_up: PowerUp) -> i32 {
+        let seconds = match power_up {
+            PowerUp::Speed => self.speed_duration,
+            PowerUp::Magnet => self.magnet_duration,
+            PowerUp::Ghost => self.ghost_duration,
+        };
+        (seconds * 1000.0 / GAME_LOOP_DELAY as f64) as i32
+    }
+
+    // Speed multiplier or magnet radius, see ActiveEffect
+    pub fn strength(&self, power_up: PowerUp) -> f64 {
+        match power_up {
+            PowerUp::Speed => self.speed_multiplier,
+            PowerUp::Magnet => self.magnet_radius,
+            PowerUp::Ghost => 1.0,
+        }
+    }
+}
+
+impl Default for PowerUpConfig {
+    fn default() -> Self {
+        PowerUpConfig {
+            speed_duration: 5.0,
+            speed_multiplier: 1.5,
+            magnet_duration: 8.0,
+            magnet_radius: 150.0,
+            ghost_duration: 4.0,
+        }
+    }
+}
+
+/// A value that moves from `small` to `large` as a snake grows
+#[derive(Clone, Copy, Debug)]
+pub struct SizeCurve {
+    pub small: f64,
+    pub large: f64,
+}
+
+/// Movement that depends on snake size. Between mass_small and mass_large nodes each
+/// curve is interpolated as small + (large - small) * t^curve_exponent.
+#[derive(Clone, Debug)]
+pub struct SnakeConfig {
+    pub mass_small: f64,
+    pub mass_large: f64,
+    pub curve_exponent: f64,
+    // Units per tick
+    pub speed: SizeCurve,
+    // Multiplier on speed while boosting
+    pub boost: SizeCurve,
+    // Degrees per tick
+    pub turn: SizeCurve,
+}
+
+impl SnakeConfig {
+    fn at(&self, curve: SizeCurve, mass: f64) -> f64 {
+        let range = (self.mass_large - self.mass_small).max(1.0);
+        let t = ((mass - self.mass_small) / range).clamp(0.0, 1.0);
+        curve.small + (curve.large - curve.small) * t.powf(self.curve_exponent)
+    }
+
+    pub fn speed(&self, mass: f64) -> f64 {
+        self.at(self.speed, mass)
+    }
+
+    pub fn boost(&self, mass: f64) -> f64 {
+        self.at(self.boost, mass)
+    }
+
+    pub fn turn(&self, mass: f64) -> f64 {
+        self.at(self.turn, mass)
+    }
+}
+
+impl Default for SnakeConfig {
+    fn default() -> Self {
+        SnakeConfig {
+            mass_small: SNAKE_INITIAL_LENGTH as f64,
+            mass_large: 500.0,
+            curve_exponent: 0.5,
+            speed: SizeCurve {
+                small: SNAKE_SPEED,
+                large: SNAKE_SPEED * 0.8,
+            },
+            boost: SizeCurve {
+                small: 4.0,
+                large: 3.0,
+            },
+            turn: SizeCurve {
+                small: 30.0,
+                large: 6.0,
+            },
+        }
+    }
+}
+
+/// What boosting costs
+#[derive(Clone, Debug)]
+pub struct BoostConfig {
+    // Nodes lost per second of boosting
+    pub mass_per_second: f64,
+    // Snakes with this many nodes or fewer can't boost
+    pub min_mass: usize,
+    // Distance the tail travels between two trail baits
+    pub trail_spacing: f64,
+    pub trail_bait_size: f64,
+}
+
+impl Default for BoostConfig {
+    fn default() -> Self {
+        BoostConfig {
+            mass_per_second: 0.6,
+            min_mass: SNAKE_INITIAL_LENGTH,
+            trail_spacing: 40.0,
+            trail_bait_size: 5.0,
+        }
+    }
+}
+
+/// Playable area of the map, snakes die when their head touches its edge
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MapConfig {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl MapConfig {
+    pub fn width(&self) -> f64 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f64 {
+        self.bottom - self.top
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.left && x <= self.right && y >= self.top && y <= self.bottom
+    }
+
+    /// The same area shrunk or grown by `scale` around its center
+    pub fn scaled(&self, scale: f64) -> MapConfig {
+        let (center_x, center_y) = self.center();
+        let half_w = self.width() * scale / 2.0;
+        let half_h = self.height() * scale / 2.0;
+        MapConfig {
+            left: center_x - half_w,
+            top: center_y - half_h,
+            right: center_x + half_w,
+            bottom: center_y + half_h,
+        }
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.left + self.right) / 2.0,
+            (self.top + self.bottom) / 2.0,
+        )
+    }
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            left: OFFSET_X,
+            top: OFFSET_Y,
+            right: TRUE_MAP_WIDTH,
+            bottom: TRUE_MAP_HEIGHT,
+        }
+    }
+}
+
+/// What happens to snakes left outside the arena when it shrinks
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutsidePolicy {
+    // Moved back inside with the boundary
+    Push,
+    Kill,
+}
+
+/// Scaling of the arena with the number of snakes in play
+#[derive(Clone, Debug)]
+pub struct ArenaConfig {
+    pub scaling: bool,
+    // Fraction of the map width and height used with no snakes at all
+    pub min_size: f64,
+    // Snakes in play at which the whole map is used
+    pub full_players: usize,
+    // Fraction of the map size the arena grows or shrinks by per second
+    pub resize_speed: f64,
+    pub outside: OutsidePolicy,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        ArenaConfig {
+            scaling: false,
+            min_size: 0.4,
+            full_players: 40,
+            resize_speed: 0.02,
+            outside: OutsidePolicy::Push,
+        }
+    }
+}
+
+/// Level of detail of other snakes, by their distance to the center of a client's view
+#[derive(Clone, Debug)]
+pub struct LodConfig {
+    // From this far on only every mid_step-th node is sent
+    pub mid_distance: f64,
+    pub mid_step: usize,
+    // From this far on only every far_step-th node, every far_interval ticks
+    pub far_distance: f64,
+    pub far_step: usize,
+    pub far_interval: u64,
+}
+
+impl Default for LodConfig {
+    fn default() -> Self {
+        LodConfig {
+            mid_distance: 500.0,
+            mid_step: 2,
+            far_distance: 900.0,
+            far_step: 4,
+            far_interval: 3,
+        }
+    }
+}
+
+/// Limits on what is sent to each client
+#[derive(Clone, Debug)]
+pub struct NetConfig {
+    // Bytes per tick a client may be sent, everything it gets counted. What
+    // doesn't fit waits for a later tick.
+    pub budget: usize,
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        NetConfig { budget: 8000 }
+    }
+}
+
+/// Rules a room is played with. Clients can ask to join a room of a given mode.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameMode {
+    // Endless free-for-all
+    FreeForAll,
+    // Timed rounds in a shrinking arena, see RoundConfig
+    Rounds,
+    // Free-for-all between teams, see TeamConfig
+    Teams,
+}
+
+impl GameMode {
+    pub fn parse(name: &str) -> Option<GameMode> {
+        match name {
+            "ffa" => Some(GameMode::FreeForAll),
+            "rounds" => Some(GameMode::Rounds),
+            "teams" => Some(GameMode::Teams),
+            _ => None,
+        }
+    }
+}
+
+/// Timing of the rounds mode
+#[derive(Clone, Debug)]
+pub struct RoundConfig {
+    pub lobby_seconds: f64,
+    pub duration_seconds: f64,
+    // Snakes (humans and bots) needed before the lobby counts down
+    pub min_players: usize,
+    // Fraction of the round after which the arena starts shrinking
+    pub shrink_start: f64,
+    // Fraction of the map width and height left when the round ends
+    pub final_size: f64,
+}
+
+impl Default for RoundConfig {
+    fn default() -> Self {
+        RoundConfig {
+            lobby_seconds: 15.0,
+            duration_seconds: 180.0,
+            min_players: 2,
+            shrink_start: 0.25,
+            final_size: 0.2,
+        }
+    }
+}
+
+/// Teams of the teams mode
+#[derive(Clone, Debug)]
+pub struct TeamConfig {
+    pub count: usize,
+    // Whether snakes of the same team kill each other or pass through
+    pub friendly_fire: bool,
+}
+
+impl Default for TeamConfig {
+    fn default() -> Self {
+        TeamConfig {
+            count: 2,
+            friendly_fire: false,
+        }
+    }
+}
+
+/// Server-controlled snakes
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+    // Bots are added until humans plus bots reach this, and removed as humans join
+    pub target: usize,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            target: BOT_TARGET_POPULATION,
+        }
+    }
+}
+
+/// Where new snakes are placed and how long they are protected
+#[derive(Clone, Debug)]
+pub struct SpawnConfig {
+    // Spawn points at least this far from every snake are taken first
+    pub safe_distance: f64,
+    // Seconds a new snake cannot die
+    pub protection: f64,
+}
+
+impl SpawnConfig {
+    pub fn protection_ticks(&self) -> i32 {
+        (self.protection * 1000.0 / GAME_LOOP_DELAY as f64) as i32
+    }
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        SpawnConfig {
+            safe_distance: SPAWN_SAFE_DISTANCE,
+            protection: SPAWN_PROTECTION_SECONDS,
+        }
+    }
+}
+
+/// Name, mode and player cap of a room
+#[derive(Clone, Debug)]
+pub struct RoomConfig {
+    pub name: String,
+    pub mode: GameMode,
+    // Human players and spectators, bots don't count
+    pub max_players: usize,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            name: "main".to_string(),
+            mode: GameMode::FreeForAll,
+            max_players: 50,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GameConfig {
+    pub room: RoomConfig,
+    pub map: MapConfig,
+    pub layout: MapLayout,
+    pub arena: ArenaConfig,
+    pub round: RoundConfig,
+    pub team: TeamConfig,
+    pub bot: BotConfig,
+    pub spawn: SpawnConfig,
+    pub bait: BaitConfig,
+    pub powerup: PowerUpConfig,
+    pub snake: SnakeConfig,
+    pub boost: BoostConfig,
+    pub lod: LodConfig,
+    pub net: NetConfig,
+}
+
+impl GameConfig {
+    /// Load one config per room from the file at `path`, falling back to a single
+    /// default room when it can't be read or declares no rooms
+    pub fn load_rooms(path: &str) -> Vec<GameConfig> {
+        let mut shared = GameConfig::default();
+        let mut rooms: Vec<GameConfig> = Vec::new();
+
+        let Ok(content) = fs::read_to_string(path) else {
+            println!("No config at {}, using defaults", path);
+            return vec![shared];
+        };
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                // Rooms start from the shared settings, not from the previous room
+                let mut room = shared.clone();
+                room.room.name = name.trim().to_string();
+                rooms.push(room);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                eprintln!("{}:{}: expected key = value", path, line_no + 1);
+                continue;
+            };
+            let config = rooms.last_mut().unwrap_or(&mut shared);
+            if let Err(e) = config.set(key.trim(), value.trim()) {
+                eprintln!("{}:{}: {}", path, line_no + 1, e);
+            }
+        }
+
+        if rooms.is_empty() {
+            rooms.push(shared);
+        }
+        rooms
+    }
+
+    /// Apply a single setting
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "room.mode" => {
+                self.room.mode =
+                    GameMode::parse(value).ok_or(format!("unknown game mode {}", value))?
+            }
+            "room.max_players" => self.room.max_players = parse(key, value)?,
+            "map.width" => {
+                let width = parse_in(key, value, MAP_MIN_SIZE, MAP_MAX_SIZE)?;
+                self.map.right = self.map.left + width;
+            }
+            "map.height" => {
+                let height = parse_in(key, value, MAP_MIN_SIZE, MAP_MAX_SIZE)?;
+                self.map.bottom = self.map.top + height;
+            }
+            "map.file" => {
+                // Replaces the bounds and layout, and turns bait zones into regions
+                let map = MapFile::load(value)?;
+                if let Some(bounds) = map.bounds {
+                    self.map = bounds;
+                }
+                self.layout = map.layout;
+                if !map.bait_zones.is_empty() {
+                    self.bait.policy = BaitPolicy::Regional;
+                    self.bait.regions = map.bait_zones;
+                }
+            }
+            "arena.scaling" => self.arena.scaling = parse(key, value)?,
+            "arena.min_size" => self.arena.min_size = parse_in(key, value, 0.0, 1.0)?,
+            "arena.full_players" => self.arena.full_players = parse(key, value)?,
+            "arena.resize_speed" => self.arena.resize_speed = parse(key, value)?,
+            "arena.outside" => {
+                self.arena.outside = match value {
+                    "push" => OutsidePolicy::Push,
+                    "kill" => OutsidePolicy::Kill,
+                    _ => return Err(format!("unknown arena.outside policy {}", value)),
+                }
+            }
+            "round.lobby" => self.round.lobby_seconds = parse(key, value)?,
+            "round.duration" => self.round.duration_seconds = parse(key, value)?,
+            "round.min_players" => self.round.min_players = parse(key, value)?,
+            "round.shrink_start" => self.round.shrink_start = parse(key, value)?,
+            "round.final_size" => self.round.final_size = parse(key, value)?,
+            "team.count" => {
+                let count: usize = parse(key, value)?;
+                if count < 2 {
+                    return Err(format!("{} must be at least 2", key));
+                }
+                self.team.count = count;
+            }
+            "team.friendly_fire" => self.team.friendly_fire = parse(key, value)?,
+            "bot.target" => self.bot.target = parse(key, value)?,
+            "spawn.safe_distance" => self.spawn.safe_distance = parse(key, value)?,
+            "spawn.protection" => self.spawn.protection = parse(key, value)?,
+            "bait.policy" => {
+                self.bait.policy = match value {
+                    "uniform" => BaitPolicy::Uniform,
+                    "regional" => BaitPolicy::Regional,
+                    "clustered" => BaitPolicy::Clustered,
+                    _ => return Err(format!("unknown bait policy {}", value)),
+                }
+            }
+            "bait.max" => self.bait.max_baits = parse_in(key, value, 0, BAIT_MAX_COUNT)?,
+            "bait.max_size" => self.bait.max_size = parse(key, value)?,
+            "bait.refill_per_tick" => {
+                self.bait.refill_per_tick = parse_in(key, value, 0.0, BAIT_MAX_REFILL_PER_TICK)?
+            }
+            "bait.refill_per_player" => self.bait.refill_per_player = parse(key, value)?,
+            "bait.region" => {
+                let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+                if fields.len() != 5 {
+                    return Err("bait.region expects left,top,right,bottom,target".to_string());
+                }
+                self.bait.regions.push(BaitRegion {
+                    left: parse(key, fields[0])?,
+                    top: parse(key, fields[1])?,
+                    right: parse(key, fields[2])?,
+                    bottom: parse(key, fields[3])?,
+                    target: parse(key, fields[4])?,
+                });
+            }
+            "bait.patch_count" => self.bait.patch_count = parse(key, value)?,
+            "bait.patch_radius" => self.bait.patch_radius = parse(key, value)?,
+            "bait.lifetime.natural" => self.bait.natural_lifetime = parse(key, value)?,
+            "bait.lifetime.corpse" => self.bait.corpse_lifetime = parse(key, value)?,
+            "bait.lifetime.trail" => self.bait.trail_lifetime = parse(key, value)?,
+            "bait.lifetime.powerup" => self.bait.powerup_lifetime = parse(key, value)?,
+            "bait.decay_start" => self.bait.decay_start = parse(key, value)?,
+            "bait.attraction_radius" => self.bait.attraction_radius = parse(key, value)?,
+            "bait.attraction_speed" => self.bait.attraction_speed = parse(key, value)?,
+            "bait.powerup_chance" => self.bait.powerup_chance = parse(key, value)?,
+            "bait.powerup_size" => self.bait.powerup_size = parse(key, value)?,
+            "powerup.speed.duration" => self.powerup.speed_duration = parse(key, value)?,
+            "powerup.speed.multiplier" => self.powerup.speed_multiplier = parse(key, value)?,
+            "powerup.magnet.duration" => self.powerup.magnet_duration = parse(key, value)?,
+            "powerup.magnet.radius" => self.powerup.magnet_radius = parse(key, value)?,
+            "powerup.ghost.duration" => self.powerup.ghost_duration = parse(key, value)?,
+            "snake.mass_small" => self.snake.mass_small = parse(key, value)?,
+            "snake.mass_large" => self.snake.mass_large = parse(key, value)?,
+            "snake.curve_exponent" => self.snake.curve_exponent = parse(key, value)?,
+            "snake.speed" => self.snake.speed = parse_curve(key, value, SNAKE_MAX_SPEED)?,
+            "snake.boost" => self.snake.boost = parse_curve(key, value, SNAKE_MAX_BOOST)?,
+            "snake.turn" => self.snake.turn = parse_curve(key, value, SNAKE_MAX_TURN)?,
+            "boost.mass_per_second" => self.boost.mass_per_second = parse(key, value)?,
+            "boost.min_mass" => {
+                // The tail is needed to drop trail baits
+                let min_mass: usize = parse(key, value)?;
+                if min_mass < 1 {
+                    return Err(format!("{} must be at least 1", key));
+                }
+                self.boost.min_mass = min_mass;
+            }
+            "boost.trail_spacing" => self.boost.trail_spacing = parse(key, value)?,
+            "boost.trail_bait_size" => self.boost.trail_bait_size = parse(key, value)?,
+            "lod.mid_distance" => self.lod.mid_distance = parse(key, value)?,
+            "lod.mid_step" => self.lod.mid_step = parse::<usize>(key, value)?.max(1),
+            "lod.far_distance" => self.lod.far_distance = parse(key, value)?,
+            "lod.far_step" => self.lod.far_step = parse::<usize>(key, value)?.max(1),
+            "lod.far_interval" => self.lod.far_interval = parse::<u64>(key, value)?.max(1),
+            "net.budget" => self.net.budget = parse(key, value)?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+// NaN and infinity parse as floats but break every range and sampler downstream
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    if value.parse::<f64>().is_ok_and(|v| !v.is_finite()) {
+        return Err(format!("invalid value {} for {}", value, key));
+    }
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, key))
+}
+
+fn parse_in<T>(key: &str, value: &str, min: T, max: T) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display,
+{
+    let parsed = parse(key, value)?;
+    if parsed < min || parsed > max {
+        return Err(format!("{} must be between {} and {}", key, min, max));
+    }
+    Ok(parsed)
+}
+
+// "small,large", each between 0 and max
+fn parse_curve(key: &str, value: &str, max: f64) -> Result<SizeCurve, String> {
+    let Some((small, large)) = value.split_once(',') else {
+        return Err(format!("{} expects small,large", key));
+    };
+    Ok(SizeCurve {
+        small: parse_in(key, small.trim(), 0.0, max)?,
+        large: parse_in(key, large.trim(), 0.0, max)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn snake_curves_hit_their_ends() {
+        let snake = SnakeConfig::default();
+        assert!(close(snake.speed(5.0), 2.0));
+        assert!(close(snake.speed(500.0), 1.6));
+        assert!(close(snake.boost(5.0), 4.0));
+        assert!(close(snake.turn(500.0), 6.0));
+    }
+
+    #[test]
+    fn snake_curves_clamp_outside_the_mass_range() {
+        let snake = SnakeConfig::default();
+        assert!(close(snake.speed(1.0), 2.0));
+        assert!(close(snake.speed(5000.0), 1.6));
+    }
+
+    #[test]
+    fn snake_curves_follow_the_exponent() {
+        // A quarter of the way with an exponent of 0.5 is half of the curve
+        let mut snake = SnakeConfig::default();
+        let mass = 5.0 + 495.0 * 0.25;
+        assert!(close(snake.speed(mass), 1.8));
+        assert!(close(snake.turn(mass), 18.0));
+
+        snake.curve_exponent = 1.0;
+        assert!(close(snake.turn(mass), 24.0));
+    }
+
+    #[test]
+    fn snake_curves_survive_an_empty_mass_range() {
+        let mut snake = SnakeConfig::default();
+        snake.mass_large = snake.mass_small;
+        assert!(snake.speed(5.0).is_finite());
+        assert!(close(snake.speed(10.0), 1.6));
+    }
+
+    #[test]
+    fn set_rejects_non_finite_values() {
+        let mut config = GameConfig::default();
+        for value in ["NaN", "inf", "-inf", "infinity"] {
+            assert!(config.set("bait.refill_per_tick", value).is_err());
+            assert!(config.set("snake.speed", &format!("{},1", value)).is_err());
+            assert!(config.set("bait.decay_start", value).is_err());
+        }
+        assert_eq!(
+            config.bait.refill_per_tick,
+            BaitConfig::default().refill_per_tick
+        );
+        assert!(config.bait.decay_start.is_finite());
+    }
+
+    #[test]
+    fn set_rejects_out_of_range_values() {
+        let mut config = GameConfig::default();
+        let width = config.map.right - config.map.left;
+        assert!(config.set("map.width", "100000").is_err());
+        assert!(config.set("map.width", "10").is_err());
+        assert_eq!(config.map.right - config.map.left, width);
+
+        assert!(config.set("bait.max", "1000000").is_err());
+        assert!(config.set("snake.boost", "4,50").is_err());
+        assert!(config.set("snake.turn", "-1,6").is_err());
+        assert!(config.set("boost.min_mass", "0").is_err());
+        assert!(config.set("team.count", "1").is_err());
+        assert!(config.set("arena.min_size", "1.5").is_err());
+        assert_eq!(config.bait.max_baits, BaitConfig::default().max_baits);
+        assert_eq!(config.boost.min_mass, BoostConfig::default().min_mass);
+    }
+
+    #[test]
+    fn set_accepts_values_in_range() {
+        let mut config = GameConfig::default();
+        config.set("map.width", "20000").unwrap();
+        config.set("bait.max", "0").unwrap();
+        config.set("snake.speed", "3, 2").unwrap();
+        config.set("boost.min_mass", "1").unwrap();
+        assert_eq!(config.map.right - config.map.left, 20000.0);
+        assert_eq!(config.bait.max_baits, 0);
+        assert!(close(config.snake.speed(5.0), 3.0));
+        assert_eq!(config.boost.min_mass, 1);
+    }
+
+    #[test]
+    fn set_rejects_unknown_keys_and_malformed_values() {
+        let mut config = GameConfig::default();
+        assert!(config.set("map.depth", "10").is_err());
+        assert!(config.set("bait.max", "lots").is_err());
+        assert!(config.set("snake.speed", "2").is_err());
+        assert!(config.set("room.mode", "battle").is_err());
+    }
+
+    #[test]
+    fn spawn_protection_converts_to_ticks() {
+        let mut config = GameConfig::default();
+        assert_eq!(config.spawn.protection_ticks(), 25);
+        config.set("spawn.protection", "4").unwrap();
+        config.set("spawn.safe_distance", "150").unwrap();
+        assert_eq!(config.spawn.protection_ticks(), 50);
+        assert_eq!(config.spawn.safe_distance, 150.0);
+    }
+}
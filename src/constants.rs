@@ -1,20 +1,29 @@
 // SNAKE
 pub const SNAKE_INITIAL_LENGTH: usize = 5;
 pub const SNAKE_SPEED: f64 = 2.0;
-pub const SNAKE_SPEED_ACCELERATE: f64 = 4.0;
 pub const SNAKE_SKIN_COLOR_RANGE: i32 = 255;
-pub const SNAKE_ROTATE_SPEED: f64 = 5.0;
 pub const SNAKE_NODE_SPACE: f64 = 0.0;
 pub const SNAKE_NODE_INITIAL_DISTANCE: f64 = 7.071; // sqrt(50)
 pub const SNAKE_INITIAL_SIZE: f64 = 17.0;
-pub const SNAKE_IT_IS_TIME_TO_SHORTER: i32 = 20;
+pub const SNAKE_MAX_SPEED: f64 = 20.0; // units per tick
+pub const SNAKE_MAX_BOOST: f64 = 10.0; // speed multiplier
+pub const SNAKE_MAX_TURN: f64 = 180.0; // degrees per tick
+pub const SNAKE_PUSH_MARGIN: f64 = 1.0; // left between a pushed head and the border
+
+// SPAWN
+pub const SPAWN_MAX_ATTEMPTS: i32 = 30;
+pub const SPAWN_SAFE_DISTANCE: f64 = 300.0;
+pub const SPAWN_BORDER_MARGIN: f64 = 700.0;
+pub const SPAWN_PROTECTION_SECONDS: f64 = 2.0;
+pub const SPAWN_OBSTACLE_CLEARANCE: f64 = 100.0;
 
 // BAIT
 pub const MAX_BAIT_COLOR_RANGE: i32 = 255;
-pub const MAX_BAIT_SIZE: i32 = 10;
 pub const MIN_BAITS: i32 = 0;
-pub const MAX_BAITS: i32 = 1000;
 pub const MAX_BAITS_SIZE_ON_DEAD: i32 = 15;
+pub const BAIT_DECAY_SYNC_TICKS: u64 = 12; // about once a second
+pub const BAIT_MAX_COUNT: usize = 10000;
+pub const BAIT_MAX_REFILL_PER_TICK: f64 = 100.0;
 
 // MAP
 pub const MAP_WIDTH: f64 = 2000.0;
@@ -25,15 +34,48 @@ pub const OFFSET_X: f64 = 800.0;
 pub const OFFSET_Y: f64 = 800.0;
 pub const TRUE_MAP_WIDTH: f64 = 3200.0;
 pub const TRUE_MAP_HEIGHT: f64 = 3200.0;
+pub const MAP_MIN_SIZE: f64 = 400.0;
+pub const MAP_MAX_SIZE: f64 = 20000.0;
 
 // GAME
 pub const GAME_LOOP_DELAY: i32 = 80;
+pub const INPUT_BUFFER_MAX: usize = 8; // sequenced inputs kept per player
 pub const SERVER_IP: &str = "0.0.0.0";
 pub const SERVER_PORT: u16 = 5000;
+pub const SERVER_CONFIG_PATH: &str = "server.cfg";
 pub const SERVER_CURRENT_UPDATE_PLAYER_METHOD: i32 = 2;
 pub const SERVER_CURRENT_SENDING_PLAYER_METHOD: i32 = 2;
 pub const SERVER_UPDATE_ENEMY_METHOD: i32 = 6;
 
+// ROOM
+pub const ROOM_MAX_COUNT: usize = 16;
+pub const ROOM_CODE_LENGTH: usize = 5;
+pub const ROOM_CLOSE_EMPTY_TICKS: u64 = 375; // 30 seconds at GAME_LOOP_DELAY
+pub const ROUND_STATUS_TICKS: u64 = 12; // about once a second
+pub const LEADERBOARD_TICKS: u64 = 12; // about once a second
+pub const LEADERBOARD_SIZE: usize = 10;
+pub const MINIMAP_TICKS: u64 = 12; // about once a second
+pub const MINIMAP_GRID_SIZE: usize = 16; // bait density cells per side
+pub const JOIN_QUEUE_MAX: usize = 50;
+pub const JOIN_QUEUE_UPDATE_DELAY: u64 = 1000; // ms
+pub const JOIN_QUEUE_TIMEOUT_SECS: u64 = 5;
+
+// BOT
+pub const BOT_TARGET_POPULATION: usize = 8;
+pub const BOT_SIGHT_RADIUS: f64 = 300.0;
+pub const BOT_DANGER_RADIUS: f64 = 80.0;
+pub const BOT_BORDER_MARGIN: f64 = 100.0;
+pub const BOT_BOOST_CHANCE: f64 = 0.02;
+pub const BOT_WANDER_JITTER: f64 = 0.3; // radians per tick
+
+// VIEW
+pub const VIEW_DEFAULT_W: u32 = 1280;
+pub const VIEW_DEFAULT_H: u32 = 720;
+pub const VIEW_MARGIN: f64 = 200.0;
+// A snake this far from the center of a view collects half the priority of a close one
+pub const NET_PRIORITY_DISTANCE: f64 = 300.0;
+pub const NET_DATAGRAM_SIZE: usize = 1200; // bytes, well under a typical MTU
+
 // COMMAND
 pub const COMM_START_NEW_MESS: &str = "$";
 pub const COMM_NEW_SNAKE: &str = "1,";
@@ -46,4 +88,31 @@ pub const COMM_UPDATE_ENEMY: &str = "6,";
 pub const COMM_DEAD_ENEMY: &str = "7,";
 pub const COMM_DIE: &str = "8,";
 pub const COMM_ENEMY_NAME: &str = "9,";
-pub const COMM_SNAKE_ACCELERATING: &str = "10,"; 
\ No newline at end of file
+pub const COMM_SNAKE_ACCELERATING: &str = "10,";
+pub const COMM_KILL_FEED: &str = "13,";
+pub const COMM_CAMERA: &str = "14,";
+pub const COMM_DESPAWN_BAITS: &str = "15,";
+pub const COMM_RESIZE_BAITS: &str = "16,";
+pub const COMM_MOVE_BAITS: &str = "17,";
+pub const COMM_POWER_UP: &str = "18,";
+pub const COMM_ROOM_NOT_FOUND: &str = "19,";
+pub const COMM_ROOM_CODE: &str = "20,";
+pub const COMM_ROOM_SETTING: &str = "22,";
+pub const COMM_QUEUE_POSITION: &str = "23,";
+pub const COMM_SERVER_FULL: &str = "24,";
+pub const COMM_ROUND_STATUS: &str = "25,";
+pub const COMM_ROUND_RESULTS: &str = "26,";
+pub const COMM_LEADERBOARD: &str = "27,";
+pub const COMM_TEAM_SCORES: &str = "28,";
+pub const COMM_APPEARANCE: &str = "29,";
+pub const COMM_OBSTACLE: &str = "30,";
+pub const COMM_SYNC_BAITS: &str = "31,";
+pub const COMM_MINIMAP_BAITS: &str = "32,";
+pub const COMM_LEAVE_ENEMY: &str = "33,";
+pub const COMM_LEAVE_BAITS: &str = "34,";
+pub const COMM_UPDATE_ENEMY_LOD: &str = "35,";
+pub const COMM_SNAPSHOT: &str = "36,";
+pub const COMM_TIME_SYNC: &str = "37,";
+pub const COMM_INPUT_ACK: &str = "38,";
+pub const COMM_MAP: &str = "39,";
+pub const COMM_MINIMAP_SNAKES: &str = "40,";
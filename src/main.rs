@@ -1,765 +1,324 @@
+mod arena;
 mod bait;
+mod bot;
 mod collision;
+mod config;
 pub mod constants;
+mod map;
+mod matchmaking;
 mod my_udp_socket;
 mod player;
+mod room;
+mod round;
 
 use std::{
     collections::HashMap,
     net::SocketAddr,
-    sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use tokio::{
-    sync::{Mutex, MutexGuard},
-    time::{self, Duration},
-};
+use tokio::sync::Mutex;
 
-use crate::bait::Bait;
-use crate::player::{Player, snake::Snake};
-use collision::{Rect, rect_intersect};
+use crate::config::GameConfig;
 use constants::*;
+use matchmaking::{JoinError, JoinQueue, JoinTarget};
 use my_udp_socket::MyUdpSocket;
-
-// Generate a random bait
-pub fn generate_bait(low: f64, high: f64) -> Bait {
-    let x = rand::random_range(low..high);
-    let y = rand::random_range(low..high);
-
-    let color = format!(
-        "{},{},{}",
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE)
-    );
-    let size = rand::random_range(0.0..MAX_BAIT_SIZE as f64);
-    Bait::new(x, y, color, size)
+use room::{Room, RoomKind};
+
+// Short code that no open room uses yet
+fn new_room_code(rooms: &[Arc<Room>]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    loop {
+        let code: String = (0..ROOM_CODE_LENGTH)
+            .map(|_| CHARS[rand::random_range(0..CHARS.len())] as char)
+            .collect();
+        if rooms.iter().all(|room| room.code() != Some(code.as_str())) {
+            return code;
+        }
+    }
 }
 
-// Generate mass baits based on a dead snake
-pub fn generate_mass_bait(snake: &Snake) -> Vec<bait::Bait> {
-    let mut new_bait_arr = Vec::new();
-    let color = format!(
-        "{},{},{}",
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-        rand::random_range(0..MAX_BAIT_COLOR_RANGE)
-    );
+async fn with_space(room: &Arc<Room>) -> Result<Arc<Room>, JoinError> {
+    if room.is_full().await {
+        Err(JoinError::Full)
+    } else {
+        Ok(Arc::clone(room))
+    }
+}
 
-    for i in (0..snake.nodes.len()).step_by(2) {
-        if i >= snake.nodes.len() - 1 {
-            break;
+// Drop routes to rooms that closed and to clients their room has dropped
+async fn prune_routes(routes: &mut HashMap<SocketAddr, Arc<Room>>) {
+    let mut gone = Vec::new();
+    for (addr, room) in routes.iter() {
+        if room.is_closed() || !room.has_client(*addr).await {
+            gone.push(*addr);
         }
-
-        let offset_x = rand::random_range(-5.0..5.0);
-        let offset_y = rand::random_range(-5.0..5.0);
-
-        let new_bait = Bait::new(
-            snake.nodes[i].x + offset_x,
-            snake.nodes[i].y + offset_y,
-            color.clone(),
-            MAX_BAITS_SIZE_ON_DEAD as f64,
-        );
-
-        new_bait_arr.push(new_bait);
     }
-
-    new_bait_arr
+    for addr in gone {
+        routes.remove(&addr);
+    }
 }
 
 /// Main game server struct
 struct GameServer {
     socket: Arc<MyUdpSocket>,
-    players: Arc<Mutex<HashMap<SocketAddr, Player>>>,
-    baits: Arc<Mutex<Vec<Bait>>>,
+    // Rooms from the config file. They stay open and serve as templates for
+    // rooms opened on demand.
+    templates: Vec<GameConfig>,
+    rooms: Mutex<Vec<Arc<Room>>>,
+    next_room_id: AtomicUsize,
 }
 
 impl GameServer {
-    /// Create a new GameServer bound to the given address
-    async fn new(bind_addr: &str) -> tokio::io::Result<Self> {
+    /// Create a new GameServer bound to the given address and open one room per config
+    async fn new(bind_addr: &str, configs: Vec<GameConfig>) -> tokio::io::Result<Self> {
         let socket = MyUdpSocket::bind(bind_addr).await?;
-        Ok(Self {
+        let server = Self {
             socket: Arc::new(socket),
-            players: Arc::new(Mutex::new(HashMap::new())),
-            baits: Arc::new(Mutex::new(Vec::new())),
-        })
-    }
-
-    /// Spawn the listening task that receives client packets
-    pub fn start_listener(self: Arc<Self>) {
-        let socket = Arc::clone(&self.socket);
-        let players = Arc::clone(&self.players);
-
-        tokio::spawn(async move {
-            let mut buf = vec![0u8; 1024];
-            loop {
-                match socket.recv_from(&mut buf).await {
-                    Ok((len, addr)) => {
-                        let data = &buf[..len];
-                        let mut players_lock = players.lock().await;
-
-                        let message = String::from_utf8_lossy(data);
-                        let splitted: Vec<&str> = message.split(',').collect();
-
-                        if !players_lock.contains_key(&addr) && splitted[0] == "9" {
-                            // let lk1 = players_lock.clone();
-                            // New player
-                            self.create_player(addr, data, players_lock).await;
-                            // Player::new(name, snake, addr)
-                            println!("New player from {}", addr);
-                        } else {
-                            // let lk2 = players_lock.clone();
-
-                            // Existing player
-                            self.handle_command(addr, data, players_lock).await;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to receive packet: {}", e);
-                    }
-                }
-            }
-        });
-    }
-
-    /// Handle incoming commands from existing players
-    async fn handle_command(
-        &self,
-        addr: SocketAddr,
-        data: &[u8],
-        mut players_lock: MutexGuard<'_, HashMap<SocketAddr, Player>>,
-    ) {
-        let message = String::from_utf8_lossy(data);
-        let splitted: Vec<&str> = message.split(',').collect();
-
-        if splitted.is_empty() {
-            return;
-        }
-
-        // println!("{}", message);
-        let lk = players_lock.clone();
-
-        // Try to find the player by address
-        let player_id_opt = players_lock.get_mut(&addr);
-        // println!("{}", player_id_opt.unwrap_or(0));
-
-        match splitted[0] {
-            "2" => {
-                // Update player's mouse position
-                if let Some(player_id) = player_id_opt {
-                    if splitted.len() >= 5 {
-                        player_id.update_last_seen();
-                        player_id.update_xy(
-                            splitted[1].parse().unwrap_or(0.0),
-                            splitted[2].parse().unwrap_or(0.0),
-                            splitted[3].parse().unwrap_or(0),
-                            splitted[4].parse().unwrap_or(0),
-                        );
-                    }
-                }
-            }
-            // "9" => {
-            //     // Player sends their name to all other players
-            //     if let Some(player) = player_id_opt {
-            //         if splitted.len() >= 2 {
-            //             let name = splitted[1].to_string();
-
-            //             // Update the player's name
-            //             player.update_player_name(name.clone());
-
-            //             // Notify all other players
-            //             let msg_enemy_name =
-            //                 format!("{}{}{}", COMM_START_NEW_MESS, COMM_ENEMY_NAME, player.id,);
-
-            //             for &i in lk.keys() {
-            //                 if i != player.addr {
-            //                     self.socket
-            //                         .send_to(msg_enemy_name.clone().as_bytes(), i)
-            //                         .await
-            //                         .unwrap();
-            //                 }
-            //             }
-            //         }
-            //     }
-            // }
-            "10" => {
-                // Player is accelerating
-                if let Some(player_id) = player_id_opt {
-                    player_id.update_player_acceleration(true);
-                };
-            }
-            "11" => {
-                // Player stops accelerating
-                if let Some(player_id) = player_id_opt {
-                    player_id.update_player_acceleration(false);
-                };
-            }
-            "12" => {
-                // Player send packet stat report
-                if let Some(player_id) = player_id_opt {
-                    player_id.update_player_pkt_stat(
-                        splitted[1].parse().unwrap_or(0),
-                        splitted[2].parse().unwrap_or(0),
-                    );
-                    println!("received pkt report");
-                };
-            }
-            d => {
-                println!("Inrecognized Command:{}", d);
+            templates: configs,
+            rooms: Mutex::new(Vec::new()),
+            next_room_id: AtomicUsize::new(0),
+        };
+        {
+            let mut rooms = server.rooms.lock().await;
+            for config in &server.templates {
+                server.open_room(&mut rooms, config.clone(), RoomKind::Permanent);
             }
         }
+        Ok(server)
     }
 
-    /// Game loop: sends updates to all clients every 10 ms
-    async fn game_loop(self: Arc<Self>) {
-        // let mut last_time = SystemTime::now();
-        let mut interval = time::interval(Duration::from_millis(GAME_LOOP_DELAY as u64));
-
+    /// Receive client packets and route them to rooms, placing new clients first
+    async fn listen(&self) {
+        let mut buf = vec![0u8; 1024];
+        // Room each client address joined
+        let mut routes: HashMap<SocketAddr, Arc<Room>> = HashMap::new();
+        let mut queue = JoinQueue::new(JOIN_QUEUE_MAX);
+        let mut last_queue_update = Instant::now();
         loop {
-            interval.tick().await;
-            let baits_c = Arc::clone(&self.baits);
-            let player_c = Arc::clone(&self.players);
-            // println!("Tick");
-            let mut new_bait_arr = Vec::new();
-
-            let mut cur_bait = baits_c.lock().await;
-            let mut msg_new_bait_arr = String::new();
-            let mut dead_players = Vec::new();
-            let mut dead_players_addr = Vec::new();
-
-            if cur_bait.len() < MAX_BAITS as usize {
-                let initial_bait = generate_bait(OFFSET_X + 10.0, TRUE_MAP_WIDTH - 10.0);
-                let cl = initial_bait.clone();
-                new_bait_arr.push(initial_bait);
-                cur_bait.push(cl);
-            }
-
-            // Update all player positions
-            let mut players_lock = player_c.lock().await;
-            for player in players_lock.values_mut() {
-                let move_x = player.move_x;
-                let move_y = player.move_y;
-                let window_w = player.window_w;
-                let window_h = player.window_h;
-                let plr_snake = player.get_snake();
-                if plr_snake.accelerate && plr_snake.nodes.len() > SNAKE_INITIAL_LENGTH {
-                    if plr_snake.accelerate_time < SNAKE_IT_IS_TIME_TO_SHORTER {
-                        plr_snake.accelerate_time += 1;
-                    } else {
-                        plr_snake.accelerate_time = 0;
-
-                        let last_node = &plr_snake.nodes[plr_snake.nodes.len() - 1];
-                        let new_bait = Bait::new(
-                            last_node.x,
-                            last_node.y,
-                            format!(
-                                "{},{},{}",
-                                rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-                                rand::random_range(0..MAX_BAIT_COLOR_RANGE),
-                                rand::random_range(0..MAX_BAIT_COLOR_RANGE)
-                            ),
-                            5.0,
-                        );
-                        let cl = new_bait.clone();
-                        cur_bait.push(new_bait);
-                        new_bait_arr.push(cl);
-
-                        // Remove the last node (make snake shorter)
-                        plr_snake.shorter();
-                    }
-                }
-
-                plr_snake.move_snake(move_x, move_y, window_w as f64, window_h as f64);
-            }
-
-            for player in players_lock.values() {
-                // If player is already dead, skip
-                if dead_players.contains(&player.id) {
-                    continue;
-                }
-
-                // Check against all other players
-                for other_player in players_lock.values() {
-                    if other_player == player {
-                        continue; // A player cannot hit itself
-                    }
-
-                    let player_j_head = Rect {
-                        top: other_player.snake.nodes[0].y - SNAKE_INITIAL_SIZE / 3.0,
-                        left: other_player.snake.nodes[0].x - SNAKE_INITIAL_SIZE / 3.0,
-                        right: other_player.snake.nodes[0].x + SNAKE_INITIAL_SIZE / 3.0,
-                        bottom: other_player.snake.nodes[0].y + SNAKE_INITIAL_SIZE / 3.0,
-                    };
-
-                    // Check collision with each node of player i
-                    let mut hit = false;
-                    for k in 0..player.snake.nodes.len() {
-                        let player_i_node = Rect {
-                            top: player.snake.nodes[k].y - SNAKE_INITIAL_SIZE / 3.0,
-                            left: player.snake.nodes[k].x - SNAKE_INITIAL_SIZE / 3.0,
-                            right: player.snake.nodes[k].x + SNAKE_INITIAL_SIZE / 3.0,
-                            bottom: player.snake.nodes[k].y + SNAKE_INITIAL_SIZE / 3.0,
-                        };
-
-                        if rect_intersect(&player_i_node, &player_j_head) {
-                            hit = true;
-
-                            // Generate baits from dead snake
-                            let new_bait_on_dead = generate_mass_bait(&other_player.snake);
-                            for bait in new_bait_on_dead {
-                                let bt_c = bait.clone();
-                                new_bait_arr.push(bait);
-                                cur_bait.push(bt_c);
-                            }
-
-                            dead_players.push(other_player.id);
-                            dead_players_addr.push(other_player.addr);
-
-                            // Notify player about death
-                            let death_msg = format!("{}8", COMM_START_NEW_MESS);
-                            if let Err(e) = self
-                                .socket
-                                .send_to(death_msg.as_bytes(), other_player.addr)
-                                .await
-                            {
-                                eprintln!("Failed to send welcome to {}: {}", other_player.addr, e);
-                            }
-
-                            break;
-                        }
-                    }
-
-                    if hit {
-                        break;
-                    }
-                }
-            }
-
-            // Inform all remaining players about dead players
-            let mut msg_dead_players = String::new();
-            for &dead_id in &dead_players {
-                msg_dead_players.push_str(&format!("{}7,{}", COMM_START_NEW_MESS, dead_id));
-            }
-
-            // Send death notifications to all players
-            if !msg_dead_players.is_empty() {
-                for player in players_lock.values() {
-                    if let Err(e) = self
-                        .socket
-                        .send_to(msg_dead_players.as_bytes(), player.addr)
-                        .await
-                    {
-                        eprintln!("Failed to send welcome to {}: {}", player.addr, e);
-                    }
-                }
-            }
-
-            // remove player
-            for ina in dead_players_addr {
-                players_lock.remove(&ina);
-            }
-
-            // Check if a player eats a bait
-            let bt_lk = cur_bait.clone();
-
-            let mut deleted_baits = Vec::new();
-            let mut cur_remove = Vec::new();
-            // let mut new_remove = Vec::new();
-            let mut msg_grown_players = String::new();
-
-            for player in players_lock.values_mut() {
-                let plr_id = player.id;
-                let player_i_head = Rect {
-                    top: player.snake.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0,
-                    left: player.snake.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0,
-                    right: player.snake.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0,
-                    bottom: player.snake.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0,
-                };
-                let bk = bt_lk.iter().enumerate();
-                for (idx, bait_tmp) in bk {
-                    let bait_rect = Rect {
-                        top: bait_tmp.y - bait_tmp.size / 2.0,
-                        left: bait_tmp.x - bait_tmp.size / 2.0,
-                        right: bait_tmp.x + bait_tmp.size / 2.0,
-                        bottom: bait_tmp.y + bait_tmp.size / 2.0,
-                    };
-
-                    if rect_intersect(&player_i_head, &bait_rect) {
-                        // Grow the snake
-                        player.grow_player_snake();
-                        msg_grown_players
-                            .push_str(&format!("{}62,{}", COMM_START_NEW_MESS, plr_id));
-                        // cur_bait.remove(idx);
-                        cur_remove.push(idx);
-                        // bait::destroy(j);
-                        deleted_baits.push(bait_tmp);
-                    }
-                }
-            }
-            cur_remove.dedup();
-
-            for i in cur_remove.iter().rev() {
-                cur_bait.remove(*i);
-            }
-            // Instantly clear to avoid multiple collision
-            for bait in &new_bait_arr {
-                msg_new_bait_arr.push_str(&format!(
-                    "{}3,{},{},{},{},",
-                    COMM_START_NEW_MESS, bait.x, bait.y, bait.size, bait.color
-                ));
-            }
-            if !msg_new_bait_arr.is_empty() {
-                for player in players_lock.values() {
-                    if let Err(e) = self
-                        .socket
-                        .send_to(msg_new_bait_arr.as_bytes(), player.addr)
-                        .await
-                    {
-                        eprintln!("Failed to send welcome to {}: {}", player.addr, e);
-                    }
-                }
-            }
-            new_bait_arr.clear();
-            msg_new_bait_arr.clear();
-
-            // Inform players about deleted baits
-            let mut msg_deleted_baits = String::new();
-            for bait in &deleted_baits {
-                msg_deleted_baits
-                    .push_str(&format!("{}4,{},{}", COMM_START_NEW_MESS, bait.x, bait.y));
-            }
-
-            // Send bait deletion and growth notifications
-            for player in players_lock.values_mut() {
-                if !msg_deleted_baits.is_empty() {
-                    if let Err(e) = self
-                        .socket
-                        .send_to(msg_deleted_baits.as_bytes(), player.addr)
-                        .await
-                    {
-                        eprintln!("Failed to send welcome to {}: {}", player.addr, e);
-                    }
-                }
-
-                if !msg_grown_players.is_empty() {
-                    if let Err(e) = self
-                        .socket
-                        .send_to(msg_grown_players.as_bytes(), player.addr)
-                        .await
+            match self.socket.recv_from(&mut buf).await {
+                Ok((len, addr)) => {
+                    let data = &buf[..len];
+                    let message = String::from_utf8_lossy(data);
+                    let splitted: Vec<&str> = message.split(',').collect();
+
+                    // Waiting clients keep their place as long as they keep sending.
+                    // Without a timer, queue updates ride on incoming packets.
+                    if last_queue_update.elapsed() >= Duration::from_millis(JOIN_QUEUE_UPDATE_DELAY)
                     {
-                        eprintln!("Failed to send welcome to {}: {}", player.addr, e);
-                    }
-                }
-            }
-
-            for player in players_lock.values_mut() {
-                let mut msg_update_player = format!("{}2,", COMM_START_NEW_MESS);
-
-                for (j, node) in player.snake.nodes.iter().enumerate() {
-                    msg_update_player.push_str(&format!("{:.4},{:.4}", node.x, node.y));
-                    if j < player.snake.nodes.len() - 1 {
-                        msg_update_player.push(',');
+                        last_queue_update = Instant::now();
+                        self.update_queue(&mut queue, &mut routes).await;
+                        prune_routes(&mut routes).await;
                     }
-                }
-                // println!("Send msg_update_player {} to{}",msg_update_player,player.addr);
-                if let Err(e) = self
-                    .socket
-                    .send_to(msg_update_player.as_bytes(), player.addr)
-                    .await
-                {
-                    eprintln!("Failed to send welcome to {}: {}", player.addr, e);
-                }
-            }
-
-            for player in players_lock.values() {
-                let mut msg_update_enemies_position = String::new();
-
-                for other_player in players_lock.values() {
-                    if player.addr == other_player.addr {
+                    if queue.touch(addr) {
                         continue;
                     }
 
-                    msg_update_enemies_position
-                        .push_str(&format!("{}6,{},", COMM_START_NEW_MESS, other_player.id));
-
-                    for (k, node) in other_player.snake.nodes.iter().enumerate() {
-                        msg_update_enemies_position
-                            .push_str(&format!("{:.4},{:.4}", node.x, node.y));
-                        if k < other_player.snake.nodes.len() - 1 {
-                            msg_update_enemies_position.push(',');
-                        }
-                    }
-                }
-
-                if !msg_update_enemies_position.is_empty() {
-                    if let Err(e) = self
-                        .socket
-                        .send_to(msg_update_enemies_position.as_bytes(), player.addr)
-                        .await
+                    // Forget clients their room has dropped or that closed, they may join again
+                    if let Some(room) = routes.get(&addr)
+                        && (room.is_closed() || !room.has_client(addr).await)
                     {
-                        eprintln!("Failed to send welcome to {}: {}", player.addr, e);
+                        routes.remove(&addr);
                     }
-                }
-            }
-            // Clean up inactive players (UDP connection management)
-            let inactive_players = self.get_inactive_players(5, players_lock.clone()).await; // 5 seconds timeout
-            let i: Vec<SocketAddr> = inactive_players.clone();
-            for id in inactive_players {
-                println!("Player {} disconnected due to inactivity", id);
-                let msg = format!("{}7,{}", COMM_START_NEW_MESS, id);
 
-                // Notify remaining players
-                for player in players_lock.values() {
-                    if player.addr != id {
-                        if let Err(e) = self
-                            .socket
-                            .send_to(msg.clone().as_bytes(), player.addr)
-                            .await
-                        {
-                            eprintln!("Failed to send welcome to {}: {}", player.addr, e);
+                    if let Some(room) = routes.get(&addr) {
+                        room.handle_packet(addr, data).await;
+                    } else if splitted[0] == "9" || splitted[0] == "14" {
+                        // "9,name[,target]" and "14,name[,target]" join a room.
+                        // Whoever is already waiting goes first.
+                        if !queue.is_empty() {
+                            self.update_queue(&mut queue, &mut routes).await;
+                        }
+                        let joined = match JoinTarget::parse(splitted.get(2).copied()) {
+                            Some(target) => match self.join(&target, addr, data).await {
+                                Err(JoinError::Full) if queue.push(addr, target, data) => {
+                                    println!("{} is waiting for a room", addr);
+                                    self.send_queue_positions(&queue).await;
+                                    continue;
+                                }
+                                joined => joined,
+                            },
+                            None => Err(JoinError::NotFound),
+                        };
+                        let msg = match joined {
+                            Ok(room) => {
+                                routes.insert(addr, room);
+                                continue;
+                            }
+                            Err(JoinError::Full) => {
+                                format!("{}{}", COMM_START_NEW_MESS, COMM_SERVER_FULL)
+                            }
+                            Err(JoinError::NotFound) => {
+                                format!("{}{}", COMM_START_NEW_MESS, COMM_ROOM_NOT_FOUND)
+                            }
+                        };
+                        if let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await {
+                            eprintln!("Failed to send join refusal to {}: {}", addr, e);
                         }
                     }
                 }
-            }
-
-            // remove player
-            for ina in i {
-                players_lock.remove(&ina);
-            }
-
-            let mut loss_players: Vec<SocketAddr> = Vec::new();
-            for plr in players_lock.values() {
-                let server_send = self.socket.stats_for(&plr.addr).await.unwrap().0;
-                let server_recv = self.socket.stats_for(&plr.addr).await.unwrap().2;
-                // println!("{} --- Send:{},Recv:{}", plr.addr, server_send, server_recv,);
-                // Found Packet loss
-                if (((plr.sent_pkt as f64 / server_recv as f64)
-                    + (server_send as f64 / plr.recv_pkt as f64))
-                    / 2.0
-                    < 0.98)
-                    && plr.sent_pkt > 200
-                {
-                    loss_players.push(plr.addr);
+                Err(e) => {
+                    eprintln!("Failed to receive packet: {}", e);
                 }
             }
-            let i: Vec<SocketAddr> = loss_players.clone();
-            let baits = cur_bait;
-            for id in i {
-                println!("Player {} is in high packet loss env, sync status:", id);
-
-                println!("Sync all current baits");
-                let mut all_bait_arr = String::new();
-                for bait in baits.iter() {
-                    all_bait_arr.push_str(&format!(
-                        "{}31,{},{},{},{},",
-                        COMM_START_NEW_MESS, bait.x, bait.y, bait.size, bait.color
-                    ));
-                }
-                // if !all_bait_arr.is_empty() {
-                //     if let Err(e) = self.socket.send_to(all_bait_arr.as_bytes(), id).await {
-                //         eprintln!("Failed to send welcome to {}: {}", id, e);
-                //     }
-                // }
-            }
         }
     }
 
-    // Remove players that haven't been seen in a while (UDP connection management)
-    pub async fn get_inactive_players(
+    /// Place a client and replay its join packet in the room it gets
+    async fn join(
         &self,
-        timeout_secs: u64,
-        players_lock: HashMap<SocketAddr, Player>,
-    ) -> Vec<SocketAddr> {
-        let mut inactive_ids = Vec::new();
-        let mut plr = players_lock;
-        let players = plr.clone().into_iter();
-
-        for (i, player) in players {
-            let elapsed = player.last_seen.elapsed().as_secs();
-            // println!("Last seen:{}", elapsed);
-            if elapsed > timeout_secs {
-                inactive_ids.push(i);
+        target: &JoinTarget,
+        addr: SocketAddr,
+        data: &[u8],
+    ) -> Result<Arc<Room>, JoinError> {
+        // A room that closed in the meantime is dropped by place_client
+        loop {
+            let room = self.place_client(target, addr).await?;
+            if room.handle_packet(addr, data).await {
+                return Ok(room);
             }
         }
-
-        // Remove inactive players
-        for id in &inactive_ids {
-            // if *id < players.len() {
-            //     players[*id] = None;
-            // }
-            plr.remove(id);
-        }
-
-        inactive_ids
     }
 
-    // Remove players that haven't been seen in a while (UDP connection management)
-    pub async fn get_pktlost_players(
+    /// Let waiting clients into rooms that have space now, in queue order, and
+    /// tell the rest where they stand
+    async fn update_queue(
         &self,
-        timeout_milis: u128,
-        players_lock: HashMap<SocketAddr, Player>,
-    ) -> Vec<SocketAddr> {
-        let mut inactive_ids = Vec::new();
-        let mut plr = players_lock;
-        let players = plr.clone().into_iter();
+        queue: &mut JoinQueue,
+        routes: &mut HashMap<SocketAddr, Arc<Room>>,
+    ) {
+        queue.expire(Duration::from_secs(JOIN_QUEUE_TIMEOUT_SECS));
+        if queue.is_empty() {
+            return;
+        }
 
-        for (i, player) in players {
-            let elapsed = player.last_seen.elapsed().as_millis();
-            // println!("Last seen:{}", elapsed);
-            if elapsed > timeout_milis {
-                inactive_ids.push(i);
+        let mut waiting = Vec::new();
+        for client in queue.drain() {
+            match self.join(&client.target, client.addr, &client.data).await {
+                Ok(room) => {
+                    println!("{} left the queue for room {}", client.addr, room.name());
+                    routes.insert(client.addr, room);
+                }
+                Err(_) => waiting.push(client),
             }
         }
+        queue.restore(waiting);
+        self.send_queue_positions(queue).await;
+    }
 
-        // Remove inactive players
-        for id in &inactive_ids {
-            // if *id < players.len() {
-            //     players[*id] = None;
-            // }
-            plr.remove(id);
+    async fn send_queue_positions(&self, queue: &JoinQueue) {
+        for (addr, position) in queue.positions() {
+            let msg = format!("{}{}{}", COMM_START_NEW_MESS, COMM_QUEUE_POSITION, position);
+            if let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await {
+                eprintln!("Failed to send queue position to {}: {}", addr, e);
+            }
         }
-
-        inactive_ids
     }
 
-    /// Handle creation of a new player
-
-    async fn create_player(
+    /// Least full open room matching the target, opening a new one from the
+    /// matching template when they are all full. A specific room id or code is
+    /// never substituted, and private rooms are only found by their code.
+    async fn place_client(
         &self,
+        target: &JoinTarget,
         addr: SocketAddr,
-        data: &[u8],
-        mut players_lock: MutexGuard<'_, HashMap<SocketAddr, Player>>,
-    ) {
-        let message = String::from_utf8_lossy(data);
-        let splitted: Vec<&str> = message.split(',').collect();
-
-        let player_id = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        println!("New player created: {}", player_id);
-
-        // Create a new snake
-        let player_snake = Snake::new(
-            SNAKE_INITIAL_LENGTH as i32,
-            rand::random_range(0..SNAKE_SKIN_COLOR_RANGE),
-            SNAKE_SPEED,
-        );
-
-        // Create the player
-        let name = splitted[1].to_string();
-
-        let new_player = Player::new(player_id, name, player_snake.clone(), addr);
-
-        // Send first snake back to the client
-        let mut msg = format!("{}1,", COMM_START_NEW_MESS);
-        for (i, node) in player_snake.nodes.iter().enumerate() {
-            msg.push_str(&format!("{:.4},{:.4}", node.x, node.y));
-            if i < player_snake.nodes.len() - 1 {
-                msg.push(',');
-            }
-        }
-
-        self.socket.send_to(msg.as_bytes(), addr).await.unwrap();
-
-        // Player sends their name to all other players
-
-        // Update the player's name
-
-        // Notify all other players
-        let msg_enemy_name = format!(
-            "{}{}{},{}",
-            COMM_START_NEW_MESS, COMM_ENEMY_NAME, new_player.id, new_player.name
-        );
-
-        for &i in players_lock.keys() {
-            if i != new_player.addr {
-                self.socket
-                    .send_to(msg_enemy_name.clone().as_bytes(), i)
-                    .await
-                    .unwrap();
+    ) -> Result<Arc<Room>, JoinError> {
+        let mut rooms = self.rooms.lock().await;
+        rooms.retain(|room| !room.is_closed());
+
+        let mode = match target {
+            JoinTarget::Room(room_id) => {
+                let room = rooms
+                    .iter()
+                    .find(|room| room.is_listed() && room.id == *room_id)
+                    .ok_or(JoinError::NotFound)?;
+                return with_space(room).await;
             }
-        }
-
-        // Prepare new enemy message for other players
-        let new_enemy_msg = format!(
-            "{}5,{},{},",
-            COMM_START_NEW_MESS, player_id, new_player.name
-        );
-
-        let mut full_enemy_msg = new_enemy_msg;
-        for (i, node) in player_snake.nodes.iter().enumerate() {
-            full_enemy_msg.push_str(&format!("{:.4},{:.4}", node.x, node.y));
-            if i < player_snake.nodes.len() - 1 {
-                full_enemy_msg.push(',');
+            JoinTarget::Code(code) => {
+                let room = rooms
+                    .iter()
+                    .find(|room| room.code() == Some(code.as_str()))
+                    .ok_or(JoinError::NotFound)?;
+                return with_space(room).await;
             }
-        }
-
-        // Send all other players to this new player
-        let mut data = String::new();
-
-        for other_player in players_lock.values() {
-            if other_player.addr != new_player.addr {
-                data.push_str(&format!(
-                    "{}{}{}",
-                    COMM_START_NEW_MESS, COMM_NEW_ENEMY, other_player.id
-                ));
-                data.push_str(&format!(",{},", other_player.name));
-
-                for (j, node) in other_player.snake.nodes.iter().enumerate() {
-                    data.push_str(&format!("{},{}", node.x, node.y));
-                    if j < other_player.snake.nodes.len() - 1 {
-                        data.push(',');
-                    }
+            JoinTarget::Private => {
+                if rooms.len() >= ROOM_MAX_COUNT {
+                    return Err(JoinError::Full);
                 }
+                let code = new_room_code(&rooms);
+                let kind = RoomKind::Private { code, owner: addr };
+                let template = self.templates.first().ok_or(JoinError::NotFound)?.clone();
+                return Ok(self.open_room(&mut rooms, template, kind));
+            }
+            JoinTarget::Mode(mode) => Some(*mode),
+            JoinTarget::Any => None,
+        };
+
+        let mut best: Option<(usize, &Arc<Room>)> = None;
+        for room in rooms.iter() {
+            if !room.is_listed() || mode.is_some_and(|mode| room.mode() != mode) {
+                continue;
+            }
+            let humans = room.humans().await;
+            if humans < room.max_players() && best.is_none_or(|(fewest, _)| humans < fewest) {
+                best = Some((humans, room));
             }
         }
-
-        if !data.is_empty() {
-            self.socket.send_to(data.as_bytes(), addr).await.unwrap();
+        if let Some((_, room)) = best {
+            return Ok(Arc::clone(room));
         }
 
-        // Send new player to all other players
-        for other_player in players_lock.values() {
-            if other_player.addr != new_player.addr {
-                self.socket
-                    .send_to(full_enemy_msg.clone().as_bytes(), other_player.addr)
-                    .await
-                    .unwrap();
-            }
+        let template = self
+            .templates
+            .iter()
+            .find(|config| mode.is_none_or(|mode| config.room.mode == mode))
+            .ok_or(JoinError::NotFound)?;
+        if rooms.len() >= ROOM_MAX_COUNT {
+            return Err(JoinError::Full);
         }
+        Ok(self.open_room(&mut rooms, template.clone(), RoomKind::Temporary))
+    }
 
-        // Send all baits to the new player
-        let bait = self.baits.lock().await;
-        for bait_info in bait.iter() {
-            let bait_msg = format!(
-                "{}3,{},{},{},{},",
-                COMM_START_NEW_MESS, bait_info.x, bait_info.y, bait_info.size, bait_info.color
-            );
-
-            self.socket
-                .send_to(bait_msg.as_bytes(), addr)
-                .await
-                .unwrap();
+    /// Start a room and its game loop
+    fn open_room(
+        &self,
+        rooms: &mut Vec<Arc<Room>>,
+        mut config: GameConfig,
+        kind: RoomKind,
+    ) -> Arc<Room> {
+        let id = self.next_room_id.fetch_add(1, Ordering::Relaxed);
+        if !matches!(kind, RoomKind::Permanent) {
+            config.room.name = format!("{}-{}", config.room.name, id);
         }
-
-        println!("Total player(s): {}", players_lock.len());
-        players_lock.insert(addr, new_player);
+        let room = Arc::new(Room::new(id, Arc::clone(&self.socket), config, kind));
+        println!("Room {} \"{}\" is open", room.id, room.name());
+        // A room whose loop stops for any reason must not take more joins
+        let game_loop = tokio::spawn(Arc::clone(&room).game_loop());
+        let stopped = Arc::clone(&room);
+        tokio::spawn(async move {
+            if let Err(e) = game_loop.await {
+                eprintln!("Room {} \"{}\" stopped: {}", stopped.id, stopped.name(), e);
+            }
+            stopped.close().await;
+        });
+        rooms.push(Arc::clone(&room));
+        room
     }
 }
 
 #[tokio::main]
 async fn main() -> tokio::io::Result<()> {
     // Bind UDP socket and create the game server
-    let server = Arc::new(GameServer::new("0.0.0.0:5000").await?);
-
-    // Start the listener task
-    server.clone().start_listener();
+    let configs = GameConfig::load_rooms(SERVER_CONFIG_PATH);
+    let server = GameServer::new("0.0.0.0:5000", configs).await?;
 
-    // Start the game loop in background
+    // Rooms run their game loops in the background, the listener runs here
     println!("Running...");
-    server.clone().game_loop().await;
+    server.listen().await;
     println!("Shutting down server...");
     Ok(())
 }
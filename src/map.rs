@@ -0,0 +1,353 @@
+// Map definition files: arena bounds, solid obstacles, spawn zones and bait zones.
+// They use the same `key = value` lines as the server config:
+//
+//   bounds = left,top,right,bottom
+//   circle = x,y,radius
+//   polygon = x1,y1,x2,y2,x3,y3,...
+//   spawn_zone = left,top,right,bottom
+//   bait_zone = left,top,right,bottom,target
+
+use std::fs;
+
+use crate::collision::Rect;
+use crate::config::{BaitRegion, MapConfig};
+use crate::constants::*;
+
+/// A solid part of the map that kills snakes running into it
+#[derive(Clone, Debug)]
+pub enum Obstacle {
+    Circle { x: f64, y: f64, radius: f64 },
+    // Corners in order, the last one connects back to the first
+    Polygon(Vec<(f64, f64)>),
+}
+
+// Closest point to (x, y) on the segment from a to b
+fn closest_on_segment(x: f64, y: f64, a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    if len_sq == 0.0 {
+        return a;
+    }
+    let t = (((x - a.0) * dx + (y - a.1) * dy) / len_sq).clamp(0.0, 1.0);
+    (a.0 + dx * t, a.1 + dy * t)
+}
+
+impl Obstacle {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Obstacle::Circle {
+                x: cx,
+                y: cy,
+                radius,
+            } => (x - cx).powi(2) + (y - cy).powi(2) <= radius * radius,
+            Obstacle::Polygon(points) => {
+                // Even-odd rule: count the edges a ray to the right crosses
+                let mut inside = false;
+                let mut j = points.len() - 1;
+                for i in 0..points.len() {
+                    let (xi, yi) = points[i];
+                    let (xj, yj) = points[j];
+                    if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+
+    /// Closest point of the obstacle's outline to (x, y)
+    pub fn closest_point(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Obstacle::Circle {
+                x: cx,
+                y: cy,
+                radius,
+            } => {
+                let (dx, dy) = (x - cx, y - cy);
+                let dist = (dx * dx + dy * dy).sqrt();
+                if dist == 0.0 {
+                    return (cx + radius, *cy);
+                }
+                (cx + dx / dist * radius, cy + dy / dist * radius)
+            }
+            Obstacle::Polygon(points) => (0..points.len())
+                .map(|i| closest_on_segment(x, y, points[i], points[(i + 1) % points.len()]))
+                .min_by(|a, b| {
+                    let dist_a = (a.0 - x).powi(2) + (a.1 - y).powi(2);
+                    let dist_b = (b.0 - x).powi(2) + (b.1 - y).powi(2);
+                    dist_a.total_cmp(&dist_b)
+                })
+                .unwrap_or((x, y)),
+        }
+    }
+
+    /// Whether a circle of `radius` around (x, y) overlaps the obstacle
+    pub fn hits(&self, x: f64, y: f64, radius: f64) -> bool {
+        if self.contains(x, y) {
+            return true;
+        }
+        let (px, py) = self.closest_point(x, y);
+        (px - x).powi(2) + (py - y).powi(2) < radius * radius
+    }
+
+    // "0,x,y,radius" or "1,x1,y1,x2,y2,..."
+    fn format(&self) -> String {
+        match self {
+            Obstacle::Circle { x, y, radius } => format!("0,{},{},{}", x, y, radius),
+            Obstacle::Polygon(points) => {
+                let coords = points
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, y))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("1,{}", coords)
+            }
+        }
+    }
+}
+
+/// What a map file adds to the empty arena
+#[derive(Clone, Debug, Default)]
+pub struct MapLayout {
+    pub obstacles: Vec<Obstacle>,
+    // Snakes spawn inside one of these, anywhere on the map when there are none
+    pub spawn_zones: Vec<Rect>,
+}
+
+impl MapLayout {
+    pub fn blocks(&self, x: f64, y: f64, radius: f64) -> bool {
+        self.obstacles.iter().any(|o| o.hits(x, y, radius))
+    }
+
+    /// One message per obstacle, sent to clients on join
+    pub fn obstacles_message(&self) -> String {
+        self.obstacles
+            .iter()
+            .map(|o| format!("{}{}{}", COMM_START_NEW_MESS, COMM_OBSTACLE, o.format()))
+            .collect()
+    }
+}
+
+/// The contents of a map file
+pub struct MapFile {
+    pub bounds: Option<MapConfig>,
+    pub layout: MapLayout,
+    pub bait_zones: Vec<BaitRegion>,
+}
+
+// Non-finite numbers would break every range and sampler downstream
+fn parse_numbers(key: &str, value: &str) -> Result<Vec<f64>, String> {
+    value
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse()
+                .ok()
+                .filter(|v: &f64| v.is_finite())
+                .ok_or(format!("invalid value {} for {}", value, key))
+        })
+        .collect()
+}
+
+impl MapFile {
+    pub fn load(path: &str) -> Result<MapFile, String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("can't read map {}: {}", path, e))?;
+        let mut map = MapFile {
+            bounds: None,
+            layout: MapLayout::default(),
+            bait_zones: Vec::new(),
+        };
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |e: String| format!("{}:{}: {}", path, line_no + 1, e);
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("expected key = value".to_string()));
+            };
+            let key = key.trim();
+            let numbers = parse_numbers(key, value).map_err(error)?;
+            match (key, numbers.as_slice()) {
+                ("bounds", &[left, top, right, bottom]) => {
+                    let sizes = [right - left, bottom - top];
+                    if sizes
+                        .iter()
+                        .any(|size| !(MAP_MIN_SIZE..=MAP_MAX_SIZE).contains(size))
+                    {
+                        return Err(error(format!(
+                            "bounds must be between {} and {} on each side",
+                            MAP_MIN_SIZE, MAP_MAX_SIZE
+                        )));
+                    }
+                    map.bounds = Some(MapConfig {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                    });
+                }
+                ("circle", &[x, y, radius]) => {
+                    map.layout.obstacles.push(Obstacle::Circle { x, y, radius });
+                }
+                ("polygon", coords) if coords.len() >= 6 && coords.len() % 2 == 0 => {
+                    let points = coords.chunks(2).map(|c| (c[0], c[1])).collect();
+                    map.layout.obstacles.push(Obstacle::Polygon(points));
+                }
+                ("spawn_zone", &[left, top, right, bottom]) if right > left && bottom > top => {
+                    map.layout.spawn_zones.push(Rect {
+                        top,
+                        left,
+                        right,
+                        bottom,
+                    });
+                }
+                ("bait_zone", &[left, top, right, bottom, target]) => {
+                    map.bait_zones.push(BaitRegion {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                        target: target as usize,
+                    });
+                }
+                ("bounds" | "circle" | "polygon" | "spawn_zone" | "bait_zone", _) => {
+                    return Err(error(format!("invalid values for {}", key)));
+                }
+                _ => return Err(error(format!("unknown map entry {}", key))),
+            }
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write `content` to a map file of its own and load it
+    fn load(name: &str, content: &str) -> Result<MapFile, String> {
+        let path = std::env::temp_dir().join(format!("{}-{}.map", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        let map = MapFile::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        map
+    }
+
+    #[test]
+    fn load_reads_every_entry() {
+        let map = load(
+            "every-entry",
+            "# comment\n\
+             bounds = 0,0,1000,800\n\
+             circle = 500,400,50 # trailing comment\n\
+             polygon = 0,0,10,0,10,10\n\
+             spawn_zone = 100,100,200,200\n\
+             bait_zone = 0,0,500,500,40\n",
+        )
+        .unwrap();
+        let bounds = map.bounds.unwrap();
+        assert_eq!((bounds.right, bounds.bottom), (1000.0, 800.0));
+        assert_eq!(map.layout.obstacles.len(), 2);
+        assert_eq!(map.layout.spawn_zones.len(), 1);
+        assert_eq!(map.bait_zones[0].target, 40);
+    }
+
+    #[test]
+    fn load_reads_the_example_map() {
+        let map = MapFile::load("maps/example.map").unwrap();
+        assert!(map.bounds.is_some());
+        assert_eq!(map.layout.obstacles.len(), 3);
+        assert_eq!(map.layout.spawn_zones.len(), 4);
+        assert_eq!(map.bait_zones.len(), 2);
+    }
+
+    #[test]
+    fn load_reports_the_line_of_an_error() {
+        let cases = [
+            (
+                "no-equals",
+                "circle = 1,2,3\nbounds 0,0,1000,1000\n",
+                ":2: expected key",
+            ),
+            ("bad-number", "circle = 1,two,3\n", ":1: invalid value"),
+            (
+                "small-bounds",
+                "\nbounds = 0,0,100,100\n",
+                ":2: bounds must be",
+            ),
+            (
+                "large-bounds",
+                "bounds = 0,0,1000,90000\n",
+                ":1: bounds must be",
+            ),
+            (
+                "infinite-bounds",
+                "bounds = 0,0,inf,inf\n",
+                ":1: invalid value",
+            ),
+            (
+                "nan-bounds",
+                "bounds = NaN,0,1000,1000\n",
+                ":1: invalid value",
+            ),
+            ("nan-circle", "circle = 1,1,NaN\n", ":1: invalid value"),
+            (
+                "short-polygon",
+                "polygon = 0,0,1,1\n",
+                ":1: invalid values for polygon",
+            ),
+            (
+                "unknown",
+                "square = 0,0,1\n",
+                ":1: unknown map entry square",
+            ),
+        ];
+        for (name, content, message) in cases {
+            let error = load(name, content).err().unwrap();
+            assert!(error.contains(message), "{}: {}", name, error);
+        }
+    }
+
+    #[test]
+    fn load_fails_on_a_missing_file() {
+        assert!(MapFile::load("maps/missing.map").is_err());
+    }
+
+    #[test]
+    fn circle_contains_its_inside() {
+        let circle = Obstacle::Circle {
+            x: 10.0,
+            y: 10.0,
+            radius: 5.0,
+        };
+        assert!(circle.contains(10.0, 10.0));
+        assert!(circle.contains(15.0, 10.0));
+        assert!(!circle.contains(14.0, 14.0));
+    }
+
+    #[test]
+    fn polygon_contains_by_the_even_odd_rule() {
+        // A U shape, open at the top between x 10 and 20
+        let u = Obstacle::Polygon(vec![
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 20.0),
+            (20.0, 20.0),
+            (20.0, 0.0),
+            (30.0, 0.0),
+            (30.0, 30.0),
+            (0.0, 30.0),
+        ]);
+        assert!(u.contains(5.0, 10.0));
+        assert!(u.contains(25.0, 10.0));
+        assert!(u.contains(15.0, 25.0));
+        assert!(!u.contains(15.0, 10.0));
+        assert!(!u.contains(40.0, 10.0));
+        assert!(!u.contains(5.0, -1.0));
+    }
+}
@@ -0,0 +1,219 @@
+// What joining clients ask for, and where they wait when there is no room for them
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::config::GameMode;
+
+/// Where a joining client wants to play: "any", a room id, a game mode, a new
+/// private room ("private") or a private room's code ("#CODE")
+#[derive(Clone)]
+pub enum JoinTarget {
+    Any,
+    Room(usize),
+    Mode(GameMode),
+    Private,
+    Code(String),
+}
+
+impl JoinTarget {
+    pub fn parse(field: Option<&str>) -> Option<JoinTarget> {
+        match field.map(str::trim) {
+            None | Some("") | Some("any") => Some(JoinTarget::Any),
+            Some("private") => Some(JoinTarget::Private),
+            Some(value) if value.starts_with('#') => {
+                Some(JoinTarget::Code(value[1..].to_uppercase()))
+            }
+            Some(value) => match value.parse() {
+                Ok(room_id) => Some(JoinTarget::Room(room_id)),
+                Err(_) => GameMode::parse(value).map(JoinTarget::Mode),
+            },
+        }
+    }
+}
+
+/// Why a client could not be placed
+pub enum JoinError {
+    // Every matching room is full, waiting may help
+    Full,
+    // Nothing matches the target
+    NotFound,
+}
+
+/// A client waiting for space, with the join packet to replay once there is some
+pub struct QueuedClient {
+    pub addr: SocketAddr,
+    pub target: JoinTarget,
+    pub data: Vec<u8>,
+    last_seen: Instant,
+}
+
+/// Clients waiting for a room, in arrival order
+pub struct JoinQueue {
+    entries: VecDeque<QueuedClient>,
+    capacity: usize,
+}
+
+impl JoinQueue {
+    pub fn new(capacity: usize) -> JoinQueue {
+        JoinQueue {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add a client to the back of the queue. Returns false when the queue is full.
+    pub fn push(&mut self, addr: SocketAddr, target: JoinTarget, data: &[u8]) -> bool {
+        if self.entries.len() >= self.capacity {
+            return false;
+        }
+        self.entries.push_back(QueuedClient {
+            addr,
+            target,
+            data: data.to_vec(),
+            last_seen: Instant::now(),
+        });
+        true
+    }
+
+    /// Note that a client is still there. Returns false if it isn't queued.
+    pub fn touch(&mut self, addr: SocketAddr) -> bool {
+        match self.entries.iter_mut().find(|c| c.addr == addr) {
+            Some(client) => {
+                client.last_seen = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop clients that haven't sent anything for `timeout`
+    pub fn expire(&mut self, timeout: Duration) {
+        self.entries.retain(|c| c.last_seen.elapsed() <= timeout);
+    }
+
+    /// Take the clients out of the queue, to put back the ones still waiting
+    pub fn drain(&mut self) -> Vec<QueuedClient> {
+        self.entries.drain(..).collect()
+    }
+
+    pub fn restore(&mut self, clients: Vec<QueuedClient>) {
+        self.entries.extend(clients);
+    }
+
+    // 1 for the client at the front
+    pub fn positions(&self) -> impl Iterator<Item = (SocketAddr, usize)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.addr, i + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_defaults_to_any() {
+        assert!(matches!(JoinTarget::parse(None), Some(JoinTarget::Any)));
+        assert!(matches!(
+            JoinTarget::parse(Some(" ")),
+            Some(JoinTarget::Any)
+        ));
+        assert!(matches!(
+            JoinTarget::parse(Some("any")),
+            Some(JoinTarget::Any)
+        ));
+    }
+
+    #[test]
+    fn parse_rooms_modes_and_codes() {
+        assert!(matches!(
+            JoinTarget::parse(Some("3")),
+            Some(JoinTarget::Room(3))
+        ));
+        assert!(matches!(
+            JoinTarget::parse(Some("teams")),
+            Some(JoinTarget::Mode(GameMode::Teams))
+        ));
+        assert!(matches!(
+            JoinTarget::parse(Some("private")),
+            Some(JoinTarget::Private)
+        ));
+        assert!(matches!(
+            JoinTarget::parse(Some("#ab3cd")),
+            Some(JoinTarget::Code(code)) if code == "AB3CD"
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_targets() {
+        assert!(JoinTarget::parse(Some("battle")).is_none());
+        assert!(JoinTarget::parse(Some("-1")).is_none());
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn queue_keeps_arrival_order() {
+        let mut queue = JoinQueue::new(10);
+        for port in [3, 1, 2] {
+            assert!(queue.push(addr(port), JoinTarget::Any, b"9,name"));
+        }
+        let positions: Vec<_> = queue.positions().collect();
+        assert_eq!(positions, vec![(addr(3), 1), (addr(1), 2), (addr(2), 3)]);
+    }
+
+    #[test]
+    fn queue_refuses_clients_when_full() {
+        let mut queue = JoinQueue::new(2);
+        assert!(queue.push(addr(1), JoinTarget::Any, b""));
+        assert!(queue.push(addr(2), JoinTarget::Any, b""));
+        assert!(!queue.push(addr(3), JoinTarget::Any, b""));
+        assert_eq!(queue.positions().count(), 2);
+    }
+
+    #[test]
+    fn queue_drops_clients_that_went_quiet() {
+        let mut queue = JoinQueue::new(10);
+        queue.push(addr(1), JoinTarget::Any, b"");
+        queue.push(addr(2), JoinTarget::Any, b"");
+        queue.push(addr(3), JoinTarget::Any, b"");
+        for client in queue.entries.iter_mut() {
+            client.last_seen = Instant::now() - Duration::from_secs(10);
+        }
+        assert!(queue.touch(addr(2)));
+        assert!(!queue.touch(addr(4)));
+
+        queue.expire(Duration::from_secs(5));
+        let positions: Vec<_> = queue.positions().collect();
+        assert_eq!(positions, vec![(addr(2), 1)]);
+    }
+
+    #[test]
+    fn queue_restores_drained_clients_in_order() {
+        let mut queue = JoinQueue::new(10);
+        for port in 1..=3 {
+            queue.push(addr(port), JoinTarget::Any, &[port as u8]);
+        }
+        let mut waiting = queue.drain();
+        assert!(queue.is_empty());
+        // The front client found a room
+        let placed = waiting.remove(0);
+        assert_eq!(placed.data, vec![1]);
+
+        queue.restore(waiting);
+        let positions: Vec<_> = queue.positions().collect();
+        assert_eq!(positions, vec![(addr(2), 1), (addr(3), 2)]);
+    }
+}
@@ -88,26 +88,6 @@ impl MyUdpSocket {
         result
     }
 
-    /// Get a snapshot of all per‐peer stats as `(sent_pkts, sent_bytes, recv_pkts, recv_bytes)`.
-    pub async fn get_stats(
-        &self,
-    ) -> HashMap<SocketAddr, (usize, usize, usize, usize)> {
-        let map = self.stats.lock().await;
-        map.iter()
-            .map(|(peer, stat)| {
-                (
-                    *peer,
-                    (
-                        stat.sent_packets(),
-                        stat.sent_bytes(),
-                        stat.received_packets(),
-                        stat.received_bytes(),
-                    ),
-                )
-            })
-            .collect()
-    }
-
     /// Convenience: get stats for one specific peer, if any.
     pub async fn stats_for(
         &self,
@@ -1,16 +1,101 @@
 pub mod snake;
 
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
 use std::time::Instant;
 
+use crate::collision::Rect;
+use crate::constants::*;
 use snake::Snake;
 // Import the real Snake type from our snake module
 
+/// Why a snake died, sent to clients as a numeric code in the kill feed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeathCause {
+    Snake,
+    Border,
+    Disconnect,
+    // Left the arena of a round, or was caught outside a shrinking one
+    Arena,
+    Obstacle,
+}
+
+impl DeathCause {
+    pub fn code(&self) -> i32 {
+        match self {
+            DeathCause::Snake => 0,
+            DeathCause::Border => 1,
+            DeathCause::Disconnect => 2,
+            DeathCause::Arena => 3,
+            DeathCause::Obstacle => 4,
+        }
+    }
+}
+
+/// Whether a player currently controls a snake
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayerState {
+    Playing,
+    Dead,
+    Spectating,
+}
+
+/// What a player without a snake is watching
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Camera {
+    Own,
+    Follow(u128),
+    Area { x: f64, y: f64 },
+}
+
+/// A single death, as reported in the kill feed
+#[derive(Clone, Debug)]
+pub struct DeathRecord {
+    pub victim_id: u128,
+    pub killer_id: Option<u128>,
+    pub victim_length: usize,
+    pub cause: DeathCause,
+}
+
+impl DeathRecord {
+    pub fn new(victim: &Player, killer_id: Option<u128>, cause: DeathCause) -> DeathRecord {
+        DeathRecord {
+            victim_id: victim.id,
+            killer_id,
+            victim_length: victim.snake.nodes.len(),
+            cause,
+        }
+    }
+}
+
+/// A message waiting for room in its client's per-tick byte budget
+#[derive(Clone, Debug)]
+pub struct Pending {
+    // Messages with the same code sent next to each other are joined into one.
+    // Without a code the text is a complete message.
+    pub code: &'static str,
+    pub text: String,
+    // Where it happened, None for news about the room or the client itself
+    pub at: Option<(f64, f64)>,
+    pub priority: f64,
+}
+
+/// A sequenced "2," input, waiting to be applied on a tick
+#[derive(Clone, Copy, Debug)]
+pub struct Input {
+    pub seq: u32,
+    pub move_x: f64,
+    pub move_y: f64,
+    pub window_w: u32,
+    pub window_h: u32,
+}
+
 pub struct Player {
     pub id: u128,
     pub name: String,
     pub snake: Snake,
-    pub addr: SocketAddr,
+    // Server controlled bots have no address
+    pub addr: Option<SocketAddr>,
     pub move_x: f64,
     pub move_y: f64,
     pub window_w: u32,
@@ -18,10 +103,37 @@ pub struct Player {
     pub last_seen: Instant,
     pub sent_pkt: u32,
     pub recv_pkt: u32,
+    pub score: u32,
+    pub kills: u32,
+    pub state: PlayerState,
+    pub last_death: Option<DeathRecord>,
+    pub camera: Camera,
+    // Only set in the teams mode, kept across respawns
+    pub team: Option<usize>,
+    // Area the client was last sent, None until its first update
+    pub view: Option<Rect>,
+    // Other snakes the client was told about and gets updates for
+    pub known_snakes: HashSet<u128>,
+    // Priority each snake in view has collected since its last update
+    pub priorities: HashMap<u128, f64>,
+    // Messages not sent yet, see Pending
+    pub pending: Vec<Pending>,
+    // Sequenced inputs not applied yet, oldest first
+    pub inputs: VecDeque<Input>,
+    // Sequence number of the last input applied, 0 before the first
+    pub last_input_seq: u32,
 }
 
 impl Player {
     pub fn new(id: u128, name: String, snake: Snake, addr: SocketAddr) -> Player {
+        Player::with_addr(id, name, snake, Some(addr))
+    }
+
+    pub fn new_bot(id: u128, name: String, snake: Snake) -> Player {
+        Player::with_addr(id, name, snake, None)
+    }
+
+    fn with_addr(id: u128, name: String, snake: Snake, addr: Option<SocketAddr>) -> Player {
         Player {
             id,
             name,
@@ -34,6 +146,18 @@ impl Player {
             last_seen: Instant::now(),
             sent_pkt: 0,
             recv_pkt: 0,
+            score: 0,
+            kills: 0,
+            state: PlayerState::Playing,
+            last_death: None,
+            camera: Camera::Own,
+            team: None,
+            view: None,
+            known_snakes: HashSet::new(),
+            priorities: HashMap::new(),
+            pending: Vec::new(),
+            inputs: VecDeque::new(),
+            last_input_seq: 0,
         }
     }
 
@@ -48,16 +172,33 @@ impl Player {
         self.window_w = win_w;
     }
 
-    pub fn update_last_seen(&mut self) {
-        self.last_seen = std::time::Instant::now();
+    /// Buffer an input to be applied on a later tick. Inputs that are not newer
+    /// than the last one received are dropped, and a full buffer drops its oldest.
+    pub fn queue_input(&mut self, input: Input) {
+        let newest = self.inputs.back().map_or(self.last_input_seq, |i| i.seq);
+        if input.seq <= newest {
+            return;
+        }
+        if self.inputs.len() >= INPUT_BUFFER_MAX {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back(input);
     }
 
-    pub fn update_player_name(&mut self, name: String) {
-        self.name = name;
+    /// Apply the oldest buffered input, one per tick
+    pub fn apply_next_input(&mut self) {
+        if let Some(input) = self.inputs.pop_front() {
+            self.update_xy(input.move_x, input.move_y, input.window_w, input.window_h);
+            self.last_input_seq = input.seq;
+        }
+    }
+
+    pub fn update_last_seen(&mut self) {
+        self.last_seen = std::time::Instant::now();
     }
 
     pub fn update_player_acceleration(&mut self, accelerate: bool) {
-        self.snake.accelerate = accelerate;
+        self.snake.set_accelerate(accelerate);
     }
 
     pub fn update_player_pkt_stat(&mut self, send_pkt: u32, recv_pkt: u32) {
@@ -68,6 +209,68 @@ impl Player {
     pub fn grow_player_snake(&mut self) {
         self.snake.grow();
     }
+
+    pub fn is_bot(&self) -> bool {
+        self.addr.is_none()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == PlayerState::Playing
+    }
+
+    // Keep the session around after death so the player can respawn,
+    // watching whoever killed them in the meantime
+    pub fn die(&mut self, death: DeathRecord) {
+        self.state = PlayerState::Dead;
+        self.snake.is_dead = true;
+        self.camera = match death.killer_id {
+            Some(killer_id) => Camera::Follow(killer_id),
+            None => Camera::Area {
+                x: self.snake.nodes[0].x,
+                y: self.snake.nodes[0].y,
+            },
+        };
+        self.last_death = Some(death);
+    }
+
+    pub fn respawn(&mut self, snake: Snake) {
+        self.snake = snake;
+        self.state = PlayerState::Playing;
+        self.camera = Camera::Own;
+    }
+
+    pub fn spectate(&mut self, camera: Camera) {
+        self.state = PlayerState::Spectating;
+        self.snake.is_dead = true;
+        self.camera = camera;
+    }
+
+    pub fn reset_score(&mut self) {
+        self.score = 0;
+        self.kills = 0;
+    }
+
+    pub fn is_teammate(&self, other: &Player) -> bool {
+        self.team.is_some() && self.team == other.team
+    }
+
+    pub fn credit_kill(&mut self, victim_length: usize) {
+        self.kills += 1;
+        self.score += victim_length as u32;
+    }
+
+    /// Queue a message for the client, sent by priority as its budget allows
+    pub fn queue(&mut self, code: &'static str, text: String, at: Option<(f64, f64)>) {
+        if self.addr.is_none() {
+            return;
+        }
+        self.pending.push(Pending {
+            code,
+            text,
+            at,
+            priority: 0.0,
+        });
+    }
 }
 
 // Implement Clone for Player
@@ -87,12 +290,89 @@ impl Clone for Player {
             last_seen: self.last_seen,
             sent_pkt: self.sent_pkt,
             recv_pkt: self.recv_pkt,
+            score: self.score,
+            kills: self.kills,
+            state: self.state,
+            last_death: self.last_death.clone(),
+            camera: self.camera,
+            team: self.team,
+            view: self.view,
+            known_snakes: self.known_snakes.clone(),
+            priorities: self.priorities.clone(),
+            pending: self.pending.clone(),
+            inputs: self.inputs.clone(),
+            last_input_seq: self.last_input_seq,
         }
     }
 }
 
 impl PartialEq for Player {
     fn eq(&self, other: &Self) -> bool {
-        self.addr == other.addr
+        self.id == other.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> Player {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 5001));
+        Player::new(1, "player".to_string(), Snake::new_at(0, 0.0, 0.0), addr)
+    }
+
+    fn input(seq: u32) -> Input {
+        Input {
+            seq,
+            move_x: seq as f64,
+            move_y: 0.0,
+            window_w: 1280,
+            window_h: 720,
+        }
+    }
+
+    fn queued(player: &Player) -> Vec<u32> {
+        player.inputs.iter().map(|i| i.seq).collect()
+    }
+
+    #[test]
+    fn inputs_apply_oldest_first() {
+        let mut player = player();
+        player.queue_input(input(1));
+        player.queue_input(input(2));
+
+        player.apply_next_input();
+        assert_eq!((player.last_input_seq, player.move_x), (1, 1.0));
+        player.apply_next_input();
+        assert_eq!((player.last_input_seq, player.move_x), (2, 2.0));
+
+        // Nothing left, the last input stays in effect
+        player.apply_next_input();
+        assert_eq!((player.last_input_seq, player.move_x), (2, 2.0));
+    }
+
+    #[test]
+    fn inputs_out_of_order_are_dropped() {
+        let mut player = player();
+        player.queue_input(input(3));
+        player.queue_input(input(2));
+        player.queue_input(input(3));
+        player.queue_input(input(5));
+        assert_eq!(queued(&player), vec![3, 5]);
+
+        player.apply_next_input();
+        player.apply_next_input();
+        player.queue_input(input(4));
+        assert!(player.inputs.is_empty());
+    }
+
+    #[test]
+    fn full_input_buffer_drops_its_oldest() {
+        let mut player = player();
+        let count = INPUT_BUFFER_MAX as u32 + 2;
+        for seq in 1..=count {
+            player.queue_input(input(seq));
+        }
+        assert_eq!(queued(&player), (3..=count).collect::<Vec<_>>());
     }
 }
@@ -1,10 +1,17 @@
-use once_cell::sync::Lazy;
-use rand::Rng;
-use std::collections::HashMap;
-use std::sync::Mutex;
-
 // Import constants from the dedicated module
+use crate::bait::PowerUp;
+use crate::config::{BoostConfig, MapConfig, SnakeConfig};
 use crate::constants::*;
+use crate::map::MapLayout;
+
+/// A power-up currently affecting the snake
+#[derive(Clone)]
+pub struct ActiveEffect {
+    pub power_up: PowerUp,
+    pub ticks_left: i32,
+    // Speed multiplier or magnet radius, depending on the power-up
+    pub strength: f64,
+}
 
 #[derive(Clone)]
 pub struct SnakeNode {
@@ -14,16 +21,22 @@ pub struct SnakeNode {
 
 #[derive(Clone)]
 pub struct Snake {
-    pub length: i32,
+    // Color, picked at random or by team
     pub skin: i32,
-    pub speed: f64,
-    pub current_speed_sec: f64,
     pub nodes: Vec<SnakeNode>,
     pub current_angle: f64,
     pub rotate_angle: f64,
     pub is_dead: bool,
+    // The player holds boost; `boosting` is whether they can afford it this tick
     pub accelerate: bool,
-    pub accelerate_time: i32,
+    pub boosting: bool,
+    // Fraction of a node owed for boosting, carried between ticks
+    pub boost_debt: f64,
+    // Where the last trail bait was dropped during this boost
+    pub last_trail: Option<(f64, f64)>,
+    // Ticks left during which the snake cannot die
+    pub spawn_protection: i32,
+    pub effects: Vec<ActiveEffect>,
 }
 fn create_first_five_nodes(initial_x: f64, initial_y: f64) -> Vec<SnakeNode> {
     let mut nodes = Vec::new();
@@ -44,28 +57,82 @@ fn create_first_five_nodes(initial_x: f64, initial_y: f64) -> Vec<SnakeNode> {
     nodes
 }
 
+// Random point inside one of the spawn zones, or the inner spawn rectangle of
+// the map when it has none. Zone points are pulled into that rectangle too, in
+// case the arena has shrunk away from the zone.
+fn random_spawn_point(map: &MapConfig, layout: &MapLayout) -> (f64, f64) {
+    let margin_x = SPAWN_BORDER_MARGIN.min(map.width() / 4.0);
+    let margin_y = SPAWN_BORDER_MARGIN.min(map.height() / 4.0);
+    if !layout.spawn_zones.is_empty() {
+        let zone = &layout.spawn_zones[rand::random_range(0..layout.spawn_zones.len())];
+        return (
+            rand::random_range(zone.left..zone.right)
+                .clamp(map.left + margin_x, map.right - margin_x),
+            rand::random_range(zone.top..zone.bottom)
+                .clamp(map.top + margin_y, map.bottom - margin_y),
+        );
+    }
+    let x = rand::random_range(map.left + margin_x..map.right - margin_x);
+    let y = rand::random_range(map.top + margin_y..map.bottom - margin_y);
+    (x, y)
+}
+
+/// Sample spawn candidates clear of obstacles and return the first one that is
+/// at least `safe_distance` away from every node of the given snakes. If
+/// none is, fall back to the candidate furthest from any node.
+pub fn find_spawn_point<'a>(
+    snakes: impl Iterator<Item = &'a Snake> + Clone,
+    map: &MapConfig,
+    layout: &MapLayout,
+    safe_distance: f64,
+) -> (f64, f64) {
+    let mut best = random_spawn_point(map, layout);
+    let mut best_dist = f64::MIN;
+
+    for _ in 0..SPAWN_MAX_ATTEMPTS {
+        let (x, y) = random_spawn_point(map, layout);
+        if layout.blocks(x, y, SPAWN_OBSTACLE_CLEARANCE) {
+            continue;
+        }
+        let closest = snakes
+            .clone()
+            .flat_map(|snake| snake.nodes.iter())
+            .map(|node| ((node.x - x).powi(2) + (node.y - y).powi(2)).sqrt())
+            .fold(f64::MAX, f64::min);
+
+        if closest >= safe_distance {
+            return (x, y);
+        }
+        if closest > best_dist {
+            best = (x, y);
+            best_dist = closest;
+        }
+    }
+
+    best
+}
+
 impl Snake {
-    pub fn new(length: i32, skin: i32, speed: f64) -> Snake {
-        let map_border_w = BORDER_WIDTH - MAP_WIDTH;
-        let map_border_h = BORDER_HEIGHT - MAP_HEIGHT;
-        let initial_x =
-            rand::random_range(map_border_w / 2.0 + 500.0..map_border_w / 2.0 + MAP_WIDTH - 500.0);
-        let initial_y =
-            rand::random_range(map_border_h / 2.0 + 500.0..map_border_h / 2.0 + MAP_HEIGHT - 500.0);
+    pub fn new(skin: i32, map: &MapConfig) -> Snake {
+        let (initial_x, initial_y) = random_spawn_point(map, &MapLayout::default());
+        Snake::new_at(skin, initial_x, initial_y)
+    }
 
+    pub fn new_at(skin: i32, initial_x: f64, initial_y: f64) -> Snake {
         let default_nodes = create_first_five_nodes(initial_x, initial_y);
 
         Snake {
-            length,
             skin,
-            speed,
-            current_speed_sec: 0.0,
             nodes: default_nodes,
             current_angle: 0.0,
             rotate_angle: 0.0,
             is_dead: false,
             accelerate: false,
-            accelerate_time: 0,
+            boosting: false,
+            boost_debt: 0.0,
+            last_trail: None,
+            spawn_protection: 0,
+            effects: Vec::new(),
         }
     }
 
@@ -83,16 +150,33 @@ impl Snake {
         self.rotate_angle = angle;
     }
 
-    pub fn rotate(&mut self) {
-        if self.rotate_angle > self.current_angle {
-            self.current_angle =
-                f64::min(self.rotate_angle, self.current_angle + SNAKE_ROTATE_SPEED);
+    // Turn towards rotate_angle by at most max_step degrees, the short way round
+    pub fn rotate(&mut self, max_step: f64) {
+        let diff = (self.rotate_angle - self.current_angle + 180.0).rem_euclid(360.0) - 180.0;
+        self.current_angle =
+            (self.current_angle + diff.clamp(-max_step, max_step)).rem_euclid(360.0);
+    }
+
+    // Speed for this tick, slower for bigger snakes (see SnakeConfig)
+    fn current_speed(&self, config: &SnakeConfig) -> f64 {
+        let mass = self.nodes.len() as f64;
+        let speed = if self.boosting {
+            config.speed(mass) * config.boost(mass)
         } else {
-            self.current_angle =
-                f64::max(self.rotate_angle, self.current_angle - SNAKE_ROTATE_SPEED);
-        }
+            config.speed(mass)
+        };
+        speed * self.speed_factor()
     }
-    pub fn move_snake(&mut self, to_x: f64, to_y: f64, center_x: f64, center_y: f64) {
+
+    pub fn move_snake(
+        &mut self,
+        to_x: f64,
+        to_y: f64,
+        center_x: f64,
+        center_y: f64,
+        config: &SnakeConfig,
+        map: &MapConfig,
+    ) {
         if SERVER_CURRENT_UPDATE_PLAYER_METHOD == 1 {
             let n = self.nodes.len();
 
@@ -116,32 +200,29 @@ impl Snake {
             self.nodes[0].y += vel_y;
 
             // Limit by MAP_BORDER
-            if self.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_X {
-                self.nodes[0].x = OFFSET_X + SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0 < map.left {
+                self.nodes[0].x = map.left + SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_Y {
-                self.nodes[0].y = OFFSET_Y + SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0 < map.top {
+                self.nodes[0].y = map.top + SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_WIDTH {
-                self.nodes[0].x = TRUE_MAP_WIDTH - SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0 > map.right {
+                self.nodes[0].x = map.right - SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_HEIGHT {
-                self.nodes[0].y = TRUE_MAP_HEIGHT - SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0 > map.bottom {
+                self.nodes[0].y = map.bottom - SNAKE_INITIAL_SIZE / 2.0;
             }
         } else if SERVER_CURRENT_UPDATE_PLAYER_METHOD == 2 {
             // new method
             let n = self.nodes.len();
+            let base_speed = self.current_speed(config);
 
             for i in (1..n).rev() {
                 let dx = self.nodes[i - 1].x - self.nodes[i].x;
                 let dy = self.nodes[i - 1].y - self.nodes[i].y;
                 let dist = (dx * dx + dy * dy).sqrt();
                 let node_dist = dist / SNAKE_NODE_INITIAL_DISTANCE;
-                let speed = (if self.accelerate {
-                    SNAKE_SPEED_ACCELERATE * SNAKE_SPEED
-                } else {
-                    SNAKE_SPEED
-                }) * node_dist;
+                let speed = base_speed * node_dist;
 
                 // Normalize direction
                 let norm_x = dx / if dist == 0.0 { 0.1 } else { dist };
@@ -156,70 +237,144 @@ impl Snake {
                 self.nodes[i].y += vel_y;
 
                 // Apply map bounds
-                if self.nodes[i].x - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_X {
-                    self.nodes[i].x = OFFSET_X + SNAKE_INITIAL_SIZE / 2.0;
+                if self.nodes[i].x - SNAKE_INITIAL_SIZE / 2.0 < map.left {
+                    self.nodes[i].x = map.left + SNAKE_INITIAL_SIZE / 2.0;
                 }
-                if self.nodes[i].y - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_Y {
-                    self.nodes[i].y = OFFSET_Y + SNAKE_INITIAL_SIZE / 2.0;
+                if self.nodes[i].y - SNAKE_INITIAL_SIZE / 2.0 < map.top {
+                    self.nodes[i].y = map.top + SNAKE_INITIAL_SIZE / 2.0;
                 }
-                if self.nodes[i].x + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_WIDTH {
-                    self.nodes[i].x = TRUE_MAP_WIDTH - SNAKE_INITIAL_SIZE / 2.0;
+                if self.nodes[i].x + SNAKE_INITIAL_SIZE / 2.0 > map.right {
+                    self.nodes[i].x = map.right - SNAKE_INITIAL_SIZE / 2.0;
                 }
-                if self.nodes[i].y + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_HEIGHT {
-                    self.nodes[i].y = TRUE_MAP_HEIGHT - SNAKE_INITIAL_SIZE / 2.0;
+                if self.nodes[i].y + SNAKE_INITIAL_SIZE / 2.0 > map.bottom {
+                    self.nodes[i].y = map.bottom - SNAKE_INITIAL_SIZE / 2.0;
                 }
             }
 
-            // Move head
+            // Move head, turning towards the mouse no faster than the snake's size allows
             let dx = to_x - center_x / 2.0;
             let dy = to_y - center_y / 2.0;
-            let dist = (dx * dx + dy * dy).sqrt();
-            let norm_x = dx / if dist == 0.0 { 1.0 } else { dist };
-            let norm_y = dy / if dist == 0.0 { 1.0 } else { dist };
+            if dx != 0.0 || dy != 0.0 {
+                self.new_rotate_angle(dy.atan2(dx).to_degrees());
+            }
+            self.rotate(config.turn(self.nodes.len() as f64));
 
-            let vel_x = norm_x
-                * if self.accelerate {
-                    SNAKE_SPEED_ACCELERATE * SNAKE_SPEED
-                } else {
-                    SNAKE_SPEED
-                };
-            let vel_y = norm_y
-                * if self.accelerate {
-                    SNAKE_SPEED_ACCELERATE * SNAKE_SPEED
-                } else {
-                    SNAKE_SPEED
-                };
+            let heading = self.current_angle.to_radians();
+            let vel_x = heading.cos() * base_speed;
+            let vel_y = heading.sin() * base_speed;
 
             self.nodes[0].x += vel_x;
             self.nodes[0].y += vel_y;
 
             // Apply map bounds to head
-            if self.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_X {
-                self.nodes[0].x = OFFSET_X + SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0 < map.left {
+                self.nodes[0].x = map.left + SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0 < OFFSET_Y {
-                self.nodes[0].y = OFFSET_Y + SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0 < map.top {
+                self.nodes[0].y = map.top + SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_WIDTH {
-                self.nodes[0].x = TRUE_MAP_WIDTH - SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0 > map.right {
+                self.nodes[0].x = map.right - SNAKE_INITIAL_SIZE / 2.0;
             }
-            if self.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0 > TRUE_MAP_HEIGHT {
-                self.nodes[0].y = TRUE_MAP_HEIGHT - SNAKE_INITIAL_SIZE / 2.0;
+            if self.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0 > map.bottom {
+                self.nodes[0].y = map.bottom - SNAKE_INITIAL_SIZE / 2.0;
             }
             // println!("Moved. head at {} {}" , self.nodes[0].x , self.nodes[0].y);
         }
     }
 
+    // Starting or stopping a boost restarts its trail. The debt is kept so
+    // tapping boost is not free.
+    pub fn set_accelerate(&mut self, accelerate: bool) {
+        if accelerate != self.accelerate {
+            self.last_trail = None;
+        }
+        self.accelerate = accelerate;
+    }
+
+    /// Pay for boosting this tick: lose mass_per_second worth of nodes and drop a
+    /// trail bait every trail_spacing units the tail travels. Snakes at min_mass
+    /// or below can't boost. Returns where trail baits should be dropped.
+    pub fn pay_boost(&mut self, config: &BoostConfig) -> Vec<(f64, f64)> {
+        self.boosting = self.accelerate && self.nodes.len() > config.min_mass;
+        if !self.boosting {
+            return Vec::new();
+        }
+
+        self.boost_debt += config.mass_per_second * GAME_LOOP_DELAY as f64 / 1000.0;
+        while self.boost_debt >= 1.0 && self.nodes.len() > config.min_mass {
+            self.shorter();
+            self.boost_debt -= 1.0;
+        }
+
+        let tail = self.nodes.last().unwrap();
+        let mut trail = Vec::new();
+        match self.last_trail {
+            None => self.last_trail = Some((tail.x, tail.y)),
+            Some((x, y)) => {
+                if ((tail.x - x).powi(2) + (tail.y - y).powi(2)).sqrt() >= config.trail_spacing {
+                    trail.push((tail.x, tail.y));
+                    self.last_trail = Some((tail.x, tail.y));
+                }
+            }
+        }
+        trail
+    }
 
     pub fn shorter(&mut self) {
         if !self.nodes.is_empty() {
             self.nodes.pop();
         }
     }
-}
 
+    // Picking up a power-up that is already active just refreshes it
+    pub fn add_effect(&mut self, power_up: PowerUp, ticks: i32, strength: f64) {
+        self.effects.retain(|e| e.power_up != power_up);
+        self.effects.push(ActiveEffect {
+            power_up,
+            ticks_left: ticks,
+            strength,
+        });
+    }
 
+    pub fn tick_effects(&mut self) {
+        for effect in self.effects.iter_mut() {
+            effect.ticks_left -= 1;
+        }
+        self.effects.retain(|e| e.ticks_left > 0);
+    }
+
+    pub fn effect(&self, power_up: PowerUp) -> Option<&ActiveEffect> {
+        self.effects.iter().find(|e| e.power_up == power_up)
+    }
+
+    pub fn speed_factor(&self) -> f64 {
+        self.effect(PowerUp::Speed).map_or(1.0, |e| e.strength)
+    }
+
+    pub fn is_ghost(&self) -> bool {
+        self.effect(PowerUp::Ghost).is_some()
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.spawn_protection > 0
+    }
 
-fn random(low: f64, high: f64) -> f64 {
-    rand::random_range(low..high)
+    // The head is clamped inside the map, so touching a clamp limit means it hit the border
+    /// Move the head off the border of an arena that shrank onto it, a little
+    /// inside so moving along the border doesn't touch it
+    pub fn push_inside(&mut self, map: &MapConfig) {
+        let reach = SNAKE_INITIAL_SIZE / 2.0 + SNAKE_PUSH_MARGIN;
+        let head = &mut self.nodes[0];
+        head.x = head.x.max(map.left + reach).min(map.right - reach);
+        head.y = head.y.max(map.top + reach).min(map.bottom - reach);
+    }
+
+    pub fn head_touches_border(&self, map: &MapConfig) -> bool {
+        let head = &self.nodes[0];
+        head.x - SNAKE_INITIAL_SIZE / 2.0 <= map.left
+            || head.y - SNAKE_INITIAL_SIZE / 2.0 <= map.top
+            || head.x + SNAKE_INITIAL_SIZE / 2.0 >= map.right
+            || head.y + SNAKE_INITIAL_SIZE / 2.0 >= map.bottom
+    }
 }
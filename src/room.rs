@@ -0,0 +1,1919 @@
+// One game arena. The server can run several of these side by side.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    time::{self, Duration},
+};
+
+use crate::arena::{ArenaScaler, edge_reached};
+use crate::bait::{Bait, BaitDecay, BaitKind, PowerUp, random_color, spawn::BaitSpawner};
+use crate::bot;
+use crate::collision::{Rect, rect_intersect};
+use crate::config::{GameConfig, GameMode, MapConfig, OutsidePolicy, RoomConfig};
+use crate::constants::*;
+use crate::my_udp_socket::MyUdpSocket;
+use crate::player::{
+    Camera, DeathCause, DeathRecord, Input, Pending, Player, PlayerState,
+    snake::{Snake, find_spawn_point},
+};
+use crate::round::{Round, RoundEvent, RoundPhase};
+
+// Generate mass baits based on a dead snake
+fn generate_mass_bait(snake: &Snake) -> Vec<Bait> {
+    let mut new_bait_arr = Vec::new();
+    let color = random_color();
+
+    for i in (0..snake.nodes.len()).step_by(2) {
+        if i >= snake.nodes.len() - 1 {
+            break;
+        }
+
+        let offset_x = rand::random_range(-5.0..5.0);
+        let offset_y = rand::random_range(-5.0..5.0);
+
+        let new_bait = Bait::new(
+            snake.nodes[i].x + offset_x,
+            snake.nodes[i].y + offset_y,
+            color.clone(),
+            MAX_BAITS_SIZE_ON_DEAD as f64,
+            BaitKind::Corpse,
+        );
+
+        new_bait_arr.push(new_bait);
+    }
+
+    new_bait_arr
+}
+
+// Build a kill feed entry: killer id (0 if none), victim id, victim length, cause, killer score
+fn kill_feed_message(death: &DeathRecord, killer_score: u32) -> String {
+    format!(
+        "{}{}{},{},{},{},{}",
+        COMM_START_NEW_MESS,
+        COMM_KILL_FEED,
+        death.killer_id.unwrap_or(0),
+        death.victim_id,
+        death.victim_length,
+        death.cause.code(),
+        killer_score
+    )
+}
+
+// Comma separated node coordinates, as used by the "1,", "2,", "5," and "6," messages
+fn format_nodes(snake: &Snake) -> String {
+    snake
+        .nodes
+        .iter()
+        .map(|node| format!("{:.4},{:.4}", node.x, node.y))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Work out where a dead or spectating player is looking. When the followed snake
+// dies the camera moves on to its killer, or stays where it died.
+fn resolve_camera(
+    player: &Player,
+    players_lock: &HashMap<u128, Player>,
+    map: &MapConfig,
+) -> (Camera, f64, f64) {
+    match player.camera {
+        Camera::Own => (
+            Camera::Area {
+                x: player.snake.nodes[0].x,
+                y: player.snake.nodes[0].y,
+            },
+            player.snake.nodes[0].x,
+            player.snake.nodes[0].y,
+        ),
+        Camera::Area { x, y } => (player.camera, x, y),
+        Camera::Follow(target_id) => {
+            let Some(target) = players_lock.values().find(|p| p.id == target_id) else {
+                let (x, y) = map.center();
+                return (Camera::Area { x, y }, x, y);
+            };
+            let (x, y) = (target.snake.nodes[0].x, target.snake.nodes[0].y);
+
+            if target.is_playing() {
+                return (player.camera, x, y);
+            }
+            match target.last_death.as_ref().and_then(|d| d.killer_id) {
+                Some(killer_id) if killer_id != target_id => (Camera::Follow(killer_id), x, y),
+                _ => (Camera::Area { x, y }, x, y),
+            }
+        }
+    }
+}
+
+// Area a client receives snakes and bait events for, based on their reported
+// window size
+fn client_view(player: &Player, camera_x: f64, camera_y: f64) -> Rect {
+    let window_w = if player.window_w == 0 {
+        VIEW_DEFAULT_W
+    } else {
+        player.window_w
+    };
+    let window_h = if player.window_h == 0 {
+        VIEW_DEFAULT_H
+    } else {
+        player.window_h
+    };
+    Rect::around(
+        camera_x,
+        camera_y,
+        window_w as f64 / 2.0 + VIEW_MARGIN,
+        window_h as f64 / 2.0 + VIEW_MARGIN,
+    )
+}
+
+fn new_bait_message(bait: &Bait) -> String {
+    format!(
+        "{}{}{},{},{},{},{}",
+        COMM_START_NEW_MESS,
+        COMM_NEW_BAIT,
+        bait.x,
+        bait.y,
+        bait.size,
+        bait.color,
+        bait.kind.code()
+    )
+}
+
+// Final standings of a round: survivors by length, then everyone else from the
+// last to be eliminated to the first. Each entry is id, kills and score.
+fn round_results(round: &Round, players_lock: &HashMap<u128, Player>) -> String {
+    let mut survivors: Vec<&Player> = round
+        .participants
+        .iter()
+        .filter_map(|id| players_lock.get(id))
+        .filter(|p| p.is_playing())
+        .collect();
+    survivors.sort_by_key(|p| std::cmp::Reverse(p.snake.nodes.len()));
+
+    let standings = survivors
+        .iter()
+        .map(|p| (p.id, p.kills, p.score))
+        .chain(round.eliminated.iter().rev().copied())
+        .map(|(id, kills, score)| format!("{},{},{}", id, kills, score))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{}{}{}", COMM_START_NEW_MESS, COMM_ROUND_RESULTS, standings)
+}
+
+// Coarse view of the whole map for minimaps: every snake in play as id, head
+// position and length, then the number of baits in each cell of a grid laid
+// over the map, row by row
+fn minimap_message(
+    players_lock: &HashMap<u128, Player>,
+    baits: &[Bait],
+    map: &MapConfig,
+) -> String {
+    let snakes = players_lock
+        .values()
+        .filter(|p| p.is_playing())
+        .map(|p| {
+            format!(
+                "{},{:.0},{:.0},{}",
+                p.id,
+                p.snake.nodes[0].x,
+                p.snake.nodes[0].y,
+                p.snake.nodes.len()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut cells = vec![0usize; MINIMAP_GRID_SIZE * MINIMAP_GRID_SIZE];
+    let cell_w = map.width() / MINIMAP_GRID_SIZE as f64;
+    let cell_h = map.height() / MINIMAP_GRID_SIZE as f64;
+    for bait in baits.iter().filter(|b| map.contains(b.x, b.y)) {
+        let col = (((bait.x - map.left) / cell_w) as usize).min(MINIMAP_GRID_SIZE - 1);
+        let row = (((bait.y - map.top) / cell_h) as usize).min(MINIMAP_GRID_SIZE - 1);
+        cells[row * MINIMAP_GRID_SIZE + col] += 1;
+    }
+    let cells = cells
+        .iter()
+        .map(|count| count.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(
+        "{}{}{}{}{}{},{}",
+        COMM_START_NEW_MESS,
+        COMM_MINIMAP_SNAKES,
+        snakes,
+        COMM_START_NEW_MESS,
+        COMM_MINIMAP_BAITS,
+        MINIMAP_GRID_SIZE,
+        cells
+    )
+}
+
+// Bounds of the playable area, sent on join and whenever they change
+fn map_message(map: &MapConfig) -> String {
+    format!(
+        "{}{}{},{},{},{}",
+        COMM_START_NEW_MESS, COMM_MAP, map.left, map.top, map.right, map.bottom
+    )
+}
+
+// Queue bait events for each client whose last sent area they happen in. Each
+// event has its position and text, joined with the others behind `code` when
+// sent, or a complete message when `code` is empty.
+fn queue_in_view(
+    code: &'static str,
+    events: &[(f64, f64, String)],
+    players_lock: &mut HashMap<u128, Player>,
+) {
+    for player in players_lock.values_mut() {
+        let Some(view) = player.view else {
+            continue;
+        };
+        for (x, y, text) in events {
+            if view.contains(*x, *y) {
+                player.queue(code, text.clone(), Some((*x, *y)));
+            }
+        }
+    }
+}
+
+// Queue the same message for every connected player
+fn broadcast(msg: &str, players_lock: &mut HashMap<u128, Player>) {
+    for player in players_lock.values_mut() {
+        player.queue("", msg.to_string(), None);
+    }
+}
+
+// The client's own snake and the last input applied to it, which lets the
+// client replay the ones after it
+fn own_update_message(player: &Player) -> String {
+    format!(
+        "{}{}{}{}{}{}",
+        COMM_START_NEW_MESS,
+        COMM_INPUT_ACK,
+        player.last_input_seq,
+        COMM_START_NEW_MESS,
+        COMM_UPDATE_SNAKE,
+        format_nodes(&player.snake)
+    )
+}
+
+fn leave_enemy_message(id: u128) -> String {
+    format!("{}{}{}", COMM_START_NEW_MESS, COMM_LEAVE_ENEMY, id)
+}
+
+// One message of a client's update for a tick
+enum Outgoing {
+    // Superseded by the next tick's, like the client's own snake
+    Current(String),
+    // Full or partial update of another snake
+    Snake(u128, String),
+    // Queued message, queued again if its datagram fails
+    Queued(Pending),
+}
+
+impl Outgoing {
+    fn code(&self) -> &str {
+        match self {
+            Outgoing::Queued(pending) => pending.code,
+            Outgoing::Current(_) | Outgoing::Snake(..) => "",
+        }
+    }
+
+    // The message as it reads on its own
+    fn message(&self) -> String {
+        match self {
+            Outgoing::Current(text) | Outgoing::Snake(_, text) => text.clone(),
+            Outgoing::Queued(pending) if pending.code.is_empty() => pending.text.clone(),
+            Outgoing::Queued(pending) => {
+                format!("{}{}{}", COMM_START_NEW_MESS, pending.code, pending.text)
+            }
+        }
+    }
+}
+
+// Pack messages into datagrams of at most NET_DATAGRAM_SIZE bytes that each
+// start with `header`, joining neighbours with the same code. A message too long
+// for that gets a datagram of its own. Returns each datagram with the range of
+// messages in it.
+fn pack_datagrams(items: &[Outgoing], header: &str) -> Vec<(Range<usize>, String)> {
+    let mut datagrams = Vec::new();
+    let mut start = 0;
+    let mut datagram = header.to_string();
+    for (i, item) in items.iter().enumerate() {
+        let message = item.message();
+        let joins = i > start && !item.code().is_empty() && item.code() == items[i - 1].code();
+        let len = if joins {
+            message.len() - COMM_START_NEW_MESS.len() - item.code().len() + 1
+        } else {
+            message.len()
+        };
+        if i > start && datagram.len() + len > NET_DATAGRAM_SIZE {
+            let full = std::mem::replace(&mut datagram, header.to_string());
+            datagrams.push((start..i, full));
+            start = i;
+            datagram.push_str(&message);
+        } else if joins {
+            datagram.push(',');
+            datagram.push_str(&message[COMM_START_NEW_MESS.len() + item.code().len()..]);
+        } else {
+            datagram.push_str(&message);
+        }
+    }
+    if items.len() > start {
+        datagrams.push((start..items.len(), datagram));
+    }
+    datagrams
+}
+
+// Position sent by a client, 0 when it isn't a finite number
+fn parse_coordinate(field: &str) -> f64 {
+    field
+        .trim()
+        .parse()
+        .ok()
+        .filter(|v: &f64| v.is_finite())
+        .unwrap_or(0.0)
+}
+
+// Milliseconds since the Unix epoch
+fn server_time() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
+// Header of everything a client is sent for one tick, so updates can be
+// ordered and interpolated
+fn snapshot_message(tick: u64) -> String {
+    format!(
+        "{}{}{},{}",
+        COMM_START_NEW_MESS,
+        COMM_SNAPSHOT,
+        tick,
+        server_time()
+    )
+}
+
+// Ids are creation timestamps, bumped when two players are created in the same millisecond
+fn new_player_id(players_lock: &HashMap<u128, Player>) -> u128 {
+    let mut player_id = server_time();
+    while players_lock.contains_key(&player_id) {
+        player_id += 1;
+    }
+    player_id
+}
+
+// New snake placed away from every snake currently in play, in its team's color,
+// protected for spawn.protection seconds
+fn new_safe_snake(
+    players_lock: &HashMap<u128, Player>,
+    config: &GameConfig,
+    team: Option<usize>,
+) -> Snake {
+    let snakes = players_lock
+        .values()
+        .filter(|p| p.is_playing())
+        .map(|p| &p.snake);
+    let (x, y) = find_spawn_point(
+        snakes,
+        &config.map,
+        &config.layout,
+        config.spawn.safe_distance,
+    );
+    let skin = match team {
+        // Team colors are spread evenly over the skin range
+        Some(team) => team as i32 * SNAKE_SKIN_COLOR_RANGE / config.team.count as i32,
+        None => rand::random_range(0..SNAKE_SKIN_COLOR_RANGE),
+    };
+    let mut snake = Snake::new_at(skin, x, y);
+    snake.spawn_protection = config.spawn.protection_ticks();
+    snake
+}
+
+// Team with the fewest players and bots, spectators aside
+fn smallest_team(players_lock: &HashMap<u128, Player>, count: usize) -> usize {
+    (0..count)
+        .min_by_key(|team| {
+            players_lock
+                .values()
+                .filter(|p| p.state != PlayerState::Spectating && p.team == Some(*team))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+// Every step-th node from the head, plus the tail so the length stays right
+fn format_decimated_nodes(snake: &Snake, step: usize) -> String {
+    let last = snake.nodes.len() - 1;
+    snake
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % step == 0 || *i == last)
+        .map(|(_, node)| format!("{:.4},{:.4}", node.x, node.y))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Update of a distant snake with every step-th node
+fn lod_update_message(player: &Player, step: usize) -> String {
+    format!(
+        "{}{}{},{},{}",
+        COMM_START_NEW_MESS,
+        COMM_UPDATE_ENEMY_LOD,
+        player.id,
+        step,
+        format_decimated_nodes(&player.snake, step)
+    )
+}
+
+// Skin and team of a snake, -1 for no team. Sent along with every new snake.
+fn appearance_message(player: &Player) -> String {
+    format!(
+        "{}{}{},{},{}",
+        COMM_START_NEW_MESS,
+        COMM_APPEARANCE,
+        player.id,
+        player.snake.skin,
+        player.team.map_or(-1, |team| team as i64)
+    )
+}
+
+// Longest snakes in play as id,length pairs, followed in the teams mode by the
+// total length of each team's living snakes
+fn leaderboard_message(players_lock: &HashMap<u128, Player>, teams: Option<usize>) -> String {
+    let mut playing: Vec<&Player> = players_lock.values().filter(|p| p.is_playing()).collect();
+    playing.sort_by_key(|p| std::cmp::Reverse(p.snake.nodes.len()));
+    let entries = playing
+        .iter()
+        .take(LEADERBOARD_SIZE)
+        .map(|p| format!("{},{}", p.id, p.snake.nodes.len()))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut msg = format!("{}{}{}", COMM_START_NEW_MESS, COMM_LEADERBOARD, entries);
+
+    if let Some(count) = teams {
+        let scores = (0..count)
+            .map(|team| {
+                playing
+                    .iter()
+                    .filter(|p| p.team == Some(team))
+                    .map(|p| p.snake.nodes.len())
+                    .sum::<usize>()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        msg.push_str(&format!(
+            "{}{}{}",
+            COMM_START_NEW_MESS, COMM_TEAM_SCORES, scores
+        ));
+    }
+    msg
+}
+
+// Settings the owner of a private room may change while it runs
+const OWNER_SETTINGS: [&str; 6] = [
+    "map.width",
+    "map.height",
+    "bait.max",
+    "bait.refill_per_tick",
+    "snake.speed",
+    "snake.boost",
+];
+
+/// How a room came to be, which decides who can find it and when it closes
+pub enum RoomKind {
+    // From the config file, always open
+    Permanent,
+    // Opened on demand when matching rooms were full, closes once empty
+    Temporary,
+    // Unlisted, joined only with its code, and closes once empty. The owner
+    // can change some settings.
+    Private { code: String, owner: SocketAddr },
+}
+
+/// One arena: its own players, baits, config and game loop. Rooms share the
+/// server socket but never see each other's players.
+pub struct Room {
+    pub id: usize,
+    pub kind: RoomKind,
+    // Name, mode and cap never change after the room opens
+    settings: RoomConfig,
+    closed: AtomicBool,
+    socket: Arc<MyUdpSocket>,
+    // The game loop works on a copy taken each tick
+    config: Mutex<GameConfig>,
+    config_changed: AtomicBool,
+    // Set while a round is being played in rounds mode
+    round_running: AtomicBool,
+    // Updated by the game loop, see current_config
+    scaler: Mutex<ArenaScaler>,
+    players: Arc<Mutex<HashMap<u128, Player>>>,
+    baits: Arc<Mutex<Vec<Bait>>>,
+}
+
+impl Room {
+    pub fn new(id: usize, socket: Arc<MyUdpSocket>, config: GameConfig, kind: RoomKind) -> Room {
+        Room {
+            id,
+            kind,
+            settings: config.room.clone(),
+            closed: AtomicBool::new(false),
+            socket,
+            config: Mutex::new(config),
+            config_changed: AtomicBool::new(false),
+            round_running: AtomicBool::new(false),
+            scaler: Mutex::new(ArenaScaler::new()),
+            players: Arc::new(Mutex::new(HashMap::new())),
+            baits: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.settings.name
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.settings.max_players
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.settings.mode
+    }
+
+    // Private rooms can't be found through matchmaking
+    pub fn is_listed(&self) -> bool {
+        !matches!(self.kind, RoomKind::Private { .. })
+    }
+
+    pub fn code(&self) -> Option<&str> {
+        match &self.kind {
+            RoomKind::Private { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Stop taking packets, for a room whose game loop has stopped
+    pub async fn close(&self) {
+        // Under the players lock, like handle_packet checks it
+        let _players_lock = self.players.lock().await;
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// Human players and spectators in the room
+    pub async fn humans(&self) -> usize {
+        self.players
+            .lock()
+            .await
+            .values()
+            .filter(|p| !p.is_bot())
+            .count()
+    }
+
+    /// Whether a client at `addr` is playing or watching in this room
+    pub async fn has_client(&self, addr: SocketAddr) -> bool {
+        self.players
+            .lock()
+            .await
+            .values()
+            .any(|p| p.addr == Some(addr))
+    }
+
+    /// Whether the room has reached its cap of human players and spectators
+    pub async fn is_full(&self) -> bool {
+        self.humans().await >= self.max_players()
+    }
+
+    /// The room's config with the map cut down to the current arena size
+    async fn current_config(&self) -> GameConfig {
+        let mut config = self.config.lock().await.clone();
+        self.scaler.lock().await.apply(&mut config);
+        config
+    }
+
+    /// Handle a packet the listener routed to this room. Returns false when the
+    /// room has closed, in which case the client has to be placed elsewhere.
+    pub async fn handle_packet(&self, addr: SocketAddr, data: &[u8]) -> bool {
+        let players_lock = self.players.lock().await;
+        // The game loop closes the room while holding the same lock
+        if self.is_closed() {
+            return false;
+        }
+
+        let message = String::from_utf8_lossy(data);
+        let splitted: Vec<&str> = message.split(',').collect();
+
+        let known = players_lock.values().any(|p| p.addr == Some(addr));
+
+        if !known && (splitted[0] == "9" || splitted[0] == "14") {
+            let config = self.current_config().await;
+            let map = config.map;
+            let round_running = self.round_running.load(Ordering::Relaxed);
+            if splitted[0] == "9" && !round_running {
+                // New player
+                self.create_player(addr, data, players_lock, &config).await;
+                println!("New player from {} in room {}", addr, self.name());
+            } else if splitted[0] == "9" {
+                // New player waits for the next round
+                self.create_spectator(addr, data, players_lock, &map, true)
+                    .await;
+                println!("New player from {} waits in room {}", addr, self.name());
+            } else {
+                // New spectator
+                self.create_spectator(addr, data, players_lock, &map, false)
+                    .await;
+                println!("New spectator from {} in room {}", addr, self.name());
+            }
+
+            // Tell the client what map it is on with its obstacles, and the code
+            // to share for private rooms
+            let mut msg = map_message(&map);
+            msg.push_str(&config.layout.obstacles_message());
+            if let Some(code) = self.code() {
+                msg.push_str(&format!(
+                    "{}{}{}",
+                    COMM_START_NEW_MESS, COMM_ROOM_CODE, code
+                ));
+            }
+            if let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await {
+                eprintln!("Failed to send room info to {}: {}", addr, e);
+            }
+        } else {
+            // Existing player
+            self.handle_command(addr, data, players_lock).await;
+        }
+        true
+    }
+
+    /// Handle incoming commands from existing players
+    async fn handle_command(
+        &self,
+        addr: SocketAddr,
+        data: &[u8],
+        mut players_lock: MutexGuard<'_, HashMap<u128, Player>>,
+    ) {
+        let message = String::from_utf8_lossy(data);
+        let splitted: Vec<&str> = message.split(',').collect();
+
+        if splitted.is_empty() {
+            return;
+        }
+
+        // println!("{}", message);
+
+        // Try to find the player by address
+        let player_id_opt = players_lock.values_mut().find(|p| p.addr == Some(addr));
+        // println!("{}", player_id_opt.unwrap_or(0));
+
+        match splitted[0] {
+            "2" => {
+                // Update player's mouse position. With a sequence number
+                // ("2,x,y,w,h,seq") it is buffered and applied on a tick.
+                if let Some(player_id) = player_id_opt
+                    && splitted.len() >= 5
+                {
+                    player_id.update_last_seen();
+                    let move_x = parse_coordinate(splitted[1]);
+                    let move_y = parse_coordinate(splitted[2]);
+                    let window_w = splitted[3].parse().unwrap_or(0);
+                    let window_h = splitted[4].parse().unwrap_or(0);
+                    match splitted.get(5).and_then(|seq| seq.trim().parse().ok()) {
+                        Some(seq) => player_id.queue_input(Input {
+                            seq,
+                            move_x,
+                            move_y,
+                            window_w,
+                            window_h,
+                        }),
+                        None => player_id.update_xy(move_x, move_y, window_w, window_h),
+                    }
+                }
+            }
+            // "9" => {
+            //     // Player sends their name to all other players
+            //     if let Some(player) = player_id_opt {
+            //         if splitted.len() >= 2 {
+            //             let name = splitted[1].to_string();
+
+            //             // Update the player's name
+            //             player.update_player_name(name.clone());
+
+            //             // Notify all other players
+            //             let msg_enemy_name =
+            //                 format!("{}{}{}", COMM_START_NEW_MESS, COMM_ENEMY_NAME, player.id,);
+
+            //             for &i in lk.keys() {
+            //                 if i != player.addr {
+            //                     self.socket
+            //                         .send_to(msg_enemy_name.clone().as_bytes(), i)
+            //                         .await
+            //                         .unwrap();
+            //                 }
+            //             }
+            //         }
+            //     }
+            // }
+            "10" => {
+                // Player is accelerating
+                if let Some(player_id) = player_id_opt {
+                    player_id.update_player_acceleration(true);
+                };
+            }
+            "11" => {
+                // Player stops accelerating
+                if let Some(player_id) = player_id_opt {
+                    player_id.update_player_acceleration(false);
+                };
+            }
+            "12" => {
+                // Player send packet stat report
+                if let Some(player_id) = player_id_opt
+                    && splitted.len() >= 3
+                {
+                    player_id.update_player_pkt_stat(
+                        splitted[1].parse().unwrap_or(0),
+                        splitted[2].parse().unwrap_or(0),
+                    );
+                    println!("received pkt report");
+                };
+            }
+            "13" => {
+                // Dead player wants a new snake without reconnecting, except
+                // during a round
+                if let Some(player) = player_id_opt
+                    && !player.is_playing()
+                    && !self.round_running.load(Ordering::Relaxed)
+                {
+                    let player_id = player.id;
+                    let config = self.current_config().await;
+                    self.respawn_player(player_id, &mut players_lock, &config)
+                        .await;
+                }
+            }
+            "14" => {
+                // Dead player stops waiting to respawn and just watches
+                if let Some(player) = player_id_opt
+                    && !player.is_playing()
+                {
+                    player.spectate(player.camera);
+                }
+            }
+            "15" => {
+                // Spectator follows the player with the given id
+                if let Some(player) = player_id_opt
+                    && !player.is_playing()
+                    && let Some(Ok(target_id)) = splitted.get(1).map(|id| id.parse())
+                {
+                    player.camera = Camera::Follow(target_id);
+                }
+            }
+            "16" => {
+                // Spectator watches a fixed area of the map
+                if let Some(player) = player_id_opt
+                    && !player.is_playing()
+                    && splitted.len() >= 3
+                {
+                    player.camera = Camera::Area {
+                        x: parse_coordinate(splitted[1]),
+                        y: parse_coordinate(splitted[2]),
+                    };
+                }
+            }
+            "17" => {
+                // Owner of a private room changes a setting: "17,key,value"
+                if let RoomKind::Private { owner, .. } = &self.kind
+                    && *owner == addr
+                    && splitted.len() >= 3
+                {
+                    let key = splitted[1].trim();
+                    // Values such as size curves contain commas themselves
+                    let value = splitted[2..].join(",");
+                    self.change_setting(key, value.trim(), &mut players_lock)
+                        .await;
+                }
+            }
+            "18" => {
+                // Time sync: "18,client_time" is answered with the client's time
+                // and the server's, so the client can work out the clock offset
+                if let Some(client_time) = splitted.get(1)
+                    && client_time.trim().parse::<f64>().is_ok()
+                {
+                    let msg = format!(
+                        "{}{}{},{}",
+                        COMM_START_NEW_MESS,
+                        COMM_TIME_SYNC,
+                        client_time.trim(),
+                        server_time()
+                    );
+                    if let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await {
+                        eprintln!("Failed to send time sync to {}: {}", addr, e);
+                    }
+                }
+            }
+            d => {
+                println!("Inrecognized Command:{}", d);
+            }
+        }
+    }
+
+    /// Advance the round, starting and ending it when due, and keep clients
+    /// informed of the phase, time left and arena about once a second
+    async fn update_round(
+        &self,
+        round: &mut Round,
+        players_lock: &mut HashMap<u128, Player>,
+        config: &GameConfig,
+        tick: u64,
+    ) {
+        // Everyone but spectators takes part, dead players included
+        let waiting: Vec<u128> = players_lock
+            .values()
+            .filter(|p| p.state != PlayerState::Spectating)
+            .map(|p| p.id)
+            .collect();
+        let alive = round
+            .participants
+            .iter()
+            .filter(|id| players_lock.get(id).is_some_and(|p| p.is_playing()))
+            .count();
+
+        let event = round.tick(waiting.len(), alive);
+        match event {
+            Some(RoundEvent::Started) => {
+                println!("Room {} round started", self.name());
+                self.round_running.store(true, Ordering::Relaxed);
+                for id in &waiting {
+                    if let Some(player) = players_lock.get_mut(id) {
+                        player.reset_score();
+                    }
+                    self.respawn_player(*id, players_lock, config).await;
+                }
+                round.participants = waiting;
+            }
+            Some(RoundEvent::Ended) => {
+                println!("Room {} round ended", self.name());
+                self.round_running.store(false, Ordering::Relaxed);
+                let msg = round_results(round, players_lock);
+                broadcast(&msg, players_lock);
+            }
+            None => {}
+        }
+
+        if event.is_some() || tick.is_multiple_of(ROUND_STATUS_TICKS) {
+            let mut msg = format!(
+                "{}{}{},{}",
+                COMM_START_NEW_MESS,
+                COMM_ROUND_STATUS,
+                round.phase.code(),
+                round.seconds_left()
+            );
+            msg.push_str(&map_message(&round.arena(&config.map)));
+            broadcast(&msg, players_lock);
+        }
+    }
+
+    /// Apply a setting from the room owner and tell everyone in the room about it
+    async fn change_setting(
+        &self,
+        key: &str,
+        value: &str,
+        players_lock: &mut HashMap<u128, Player>,
+    ) {
+        if !OWNER_SETTINGS.contains(&key) {
+            println!("Room {} owner can't change {}", self.name(), key);
+            return;
+        }
+        if let Err(e) = self.config.lock().await.set(key, value) {
+            println!("Room {}: {}", self.name(), e);
+            return;
+        }
+        let map = self.current_config().await.map;
+        self.config_changed.store(true, Ordering::Relaxed);
+        println!("Room {} set {} = {}", self.name(), key, value);
+
+        let mut msg = format!(
+            "{}{}{},{}",
+            COMM_START_NEW_MESS, COMM_ROOM_SETTING, key, value
+        );
+        if key.starts_with("map.") {
+            msg.push_str(&map_message(&map));
+        }
+        broadcast(&msg, players_lock);
+    }
+
+    /// Game loop: sends updates to all clients every 10 ms
+    pub async fn game_loop(self: Arc<Self>) {
+        // let mut last_time = SystemTime::now();
+        let mut interval = time::interval(Duration::from_millis(GAME_LOOP_DELAY as u64));
+        let mut bait_spawner = {
+            let config = self.config.lock().await;
+            BaitSpawner::new(config.bait.clone(), config.map, config.layout.clone())
+        };
+        let mut tick: u64 = 0;
+        let mut empty_ticks: u64 = 0;
+        let mut sent_map = self.config.lock().await.map;
+        // The arena as it was last tick, to tell which snakes its edge moved onto
+        let mut last_map = sent_map;
+        let mut round = match self.mode() {
+            GameMode::Rounds => Some(Round::new(self.config.lock().await.round.clone())),
+            GameMode::FreeForAll | GameMode::Teams => None,
+        };
+
+        loop {
+            interval.tick().await;
+            tick += 1;
+            let baits_c = Arc::clone(&self.baits);
+            let player_c = Arc::clone(&self.players);
+            // println!("Tick");
+            let mut new_bait_arr = Vec::new();
+
+            // Players before config and baits, the same order as joining clients lock them
+            let mut players_lock = player_c.lock().await;
+
+            // Grow or shrink the arena with the snakes in play
+            let snakes = players_lock
+                .values()
+                .filter(|p| p.state != PlayerState::Spectating)
+                .count();
+            let arena_config = self.config.lock().await.arena.clone();
+            self.scaler.lock().await.update(&arena_config, snakes);
+            let config = self.current_config().await;
+            if self.config_changed.swap(false, Ordering::Relaxed) {
+                bait_spawner =
+                    BaitSpawner::new(config.bait.clone(), config.map, config.layout.clone());
+            } else {
+                bait_spawner.resize(config.bait.clone(), config.map);
+            }
+            // Rounds send their arena with the round status instead
+            if round.is_none() && config.map != sent_map && tick.is_multiple_of(ROUND_STATUS_TICKS)
+            {
+                broadcast(&map_message(&config.map), &mut players_lock);
+                sent_map = config.map;
+            }
+
+            let mut cur_bait = baits_c.lock().await;
+
+            if !matches!(self.kind, RoomKind::Permanent) {
+                if players_lock.values().any(|p| !p.is_bot()) {
+                    empty_ticks = 0;
+                } else {
+                    empty_ticks += 1;
+                }
+                if empty_ticks >= ROOM_CLOSE_EMPTY_TICKS {
+                    self.closed.store(true, Ordering::Relaxed);
+                    println!("Room {} \"{}\" closed", self.id, self.name());
+                    return;
+                }
+            }
+
+            // Age baits: shrink old ones and despawn expired ones in one batch, together
+            // with any left outside a map that shrank
+            let mut despawned_baits = Vec::new();
+            let mut resized_baits = Vec::new();
+            let sync_sizes = tick.is_multiple_of(BAIT_DECAY_SYNC_TICKS);
+            cur_bait.retain_mut(|bait| match bait.decay(&config.bait) {
+                _ if !config.map.contains(bait.x, bait.y) => {
+                    despawned_baits.push((bait.x, bait.y, format!("{},{}", bait.x, bait.y)));
+                    false
+                }
+                BaitDecay::Expired => {
+                    despawned_baits.push((bait.x, bait.y, format!("{},{}", bait.x, bait.y)));
+                    false
+                }
+                BaitDecay::Shrunk if sync_sizes => {
+                    let event = format!("{},{},{}", bait.x, bait.y, bait.size);
+                    resized_baits.push((bait.x, bait.y, event));
+                    true
+                }
+                BaitDecay::Shrunk | BaitDecay::Fresh => true,
+            });
+            queue_in_view(COMM_DESPAWN_BAITS, &despawned_baits, &mut players_lock);
+            queue_in_view(COMM_RESIZE_BAITS, &resized_baits, &mut players_lock);
+
+            let active_players = players_lock.values().filter(|p| p.is_playing()).count();
+            for bait in bait_spawner.spawn(&cur_bait, active_players) {
+                let cl = bait.clone();
+                new_bait_arr.push(bait);
+                cur_bait.push(cl);
+            }
+
+            if let Some(round) = &mut round {
+                self.update_round(round, &mut players_lock, &config, tick)
+                    .await;
+            }
+            // Nobody dies in the lobby, and during a round snakes must stay in its arena
+            let in_lobby = round.as_ref().is_some_and(|r| r.phase == RoundPhase::Lobby);
+            let arena = round.as_ref().map(|r| r.arena(&config.map));
+
+            // Top the arena up with bots while few humans are online, but not mid-round
+            if round.as_ref().is_none_or(|r| r.phase == RoundPhase::Lobby) {
+                self.balance_bots(&mut players_lock, &config).await;
+            }
+            if tick.is_multiple_of(LEADERBOARD_TICKS) {
+                let msg = leaderboard_message(&players_lock, self.team_count(&config));
+                broadcast(&msg, &mut players_lock);
+            }
+            if tick.is_multiple_of(MINIMAP_TICKS) {
+                let msg = minimap_message(&players_lock, &cur_bait, &config.map);
+                broadcast(&msg, &mut players_lock);
+            }
+
+            let decisions: Vec<(u128, bot::BotDecision)> = players_lock
+                .values()
+                .filter(|p| p.is_bot() && p.is_playing())
+                .map(|p| {
+                    let bounds = arena.as_ref().unwrap_or(&config.map);
+                    (
+                        p.id,
+                        bot::think(p, &players_lock, &cur_bait, &config, bounds),
+                    )
+                })
+                .collect();
+            for (id, decision) in decisions {
+                if let Some(bot) = players_lock.get_mut(&id) {
+                    bot.update_xy(decision.dir_x, decision.dir_y, 0, 0);
+                    bot.update_player_acceleration(decision.accelerate);
+                }
+            }
+
+            // Snakes the edge of the arena moved onto this tick. The push policy moves
+            // them back inside and spares them the border check; the kill policy kills them.
+            let mut pushed: Vec<u128> = Vec::new();
+            for player in players_lock.values_mut() {
+                if player.is_playing() && edge_reached(&player.snake, &last_map, &config.map) {
+                    if config.arena.outside == OutsidePolicy::Push {
+                        player.snake.push_inside(&config.map);
+                    }
+                    pushed.push(player.id);
+                }
+            }
+            last_map = config.map;
+
+            // Update all player positions
+            for player in players_lock.values_mut() {
+                player.apply_next_input();
+                if !player.is_playing() {
+                    continue;
+                }
+                let move_x = player.move_x;
+                let move_y = player.move_y;
+                let window_w = player.window_w;
+                let window_h = player.window_h;
+                let plr_snake = player.get_snake();
+                if plr_snake.is_protected() {
+                    plr_snake.spawn_protection -= 1;
+                }
+                plr_snake.tick_effects();
+                for (trail_x, trail_y) in plr_snake.pay_boost(&config.boost) {
+                    let new_bait = Bait::new(
+                        trail_x,
+                        trail_y,
+                        random_color(),
+                        config.boost.trail_bait_size,
+                        BaitKind::Trail,
+                    );
+                    let cl = new_bait.clone();
+                    cur_bait.push(new_bait);
+                    new_bait_arr.push(cl);
+                }
+
+                plr_snake.move_snake(
+                    move_x,
+                    move_y,
+                    window_w as f64,
+                    window_h as f64,
+                    &config.snake,
+                    &config.map,
+                );
+            }
+
+            // Collect every death of this tick together with who caused it
+            let mut deaths: Vec<DeathRecord> = Vec::new();
+
+            for player in players_lock.values() {
+                let pushed = pushed.contains(&player.id);
+                if pushed && config.arena.outside == OutsidePolicy::Kill {
+                    deaths.push(DeathRecord::new(player, None, DeathCause::Arena));
+                } else if player.is_playing()
+                    && !player.snake.is_protected()
+                    && !pushed
+                    && player.snake.head_touches_border(&config.map)
+                {
+                    deaths.push(DeathRecord::new(player, None, DeathCause::Border));
+                } else if player.is_playing()
+                    && let Some(arena) = &arena
+                    && !arena.contains(player.snake.nodes[0].x, player.snake.nodes[0].y)
+                {
+                    deaths.push(DeathRecord::new(player, None, DeathCause::Arena));
+                } else if player.is_playing()
+                    && !player.snake.is_protected()
+                    && config.layout.blocks(
+                        player.snake.nodes[0].x,
+                        player.snake.nodes[0].y,
+                        SNAKE_INITIAL_SIZE / 2.0,
+                    )
+                {
+                    deaths.push(DeathRecord::new(player, None, DeathCause::Obstacle));
+                }
+            }
+
+            for player in players_lock.values() {
+                // If player is already dead, skip
+                if !player.is_playing() || deaths.iter().any(|d| d.victim_id == player.id) {
+                    continue;
+                }
+
+                // Check against all other players
+                for other_player in players_lock.values() {
+                    if other_player == player {
+                        continue; // A player cannot hit itself
+                    }
+                    // Teammates pass through each other unless friendly fire is on
+                    if player.is_teammate(other_player) && !config.team.friendly_fire {
+                        continue;
+                    }
+                    // Ghosts pass through other snakes
+                    if !other_player.is_playing()
+                        || other_player.snake.is_protected()
+                        || other_player.snake.is_ghost()
+                        || deaths.iter().any(|d| d.victim_id == other_player.id)
+                    {
+                        continue;
+                    }
+
+                    let player_j_head = Rect {
+                        top: other_player.snake.nodes[0].y - SNAKE_INITIAL_SIZE / 3.0,
+                        left: other_player.snake.nodes[0].x - SNAKE_INITIAL_SIZE / 3.0,
+                        right: other_player.snake.nodes[0].x + SNAKE_INITIAL_SIZE / 3.0,
+                        bottom: other_player.snake.nodes[0].y + SNAKE_INITIAL_SIZE / 3.0,
+                    };
+
+                    // Check collision with each node of player i
+                    for k in 0..player.snake.nodes.len() {
+                        let player_i_node = Rect {
+                            top: player.snake.nodes[k].y - SNAKE_INITIAL_SIZE / 3.0,
+                            left: player.snake.nodes[k].x - SNAKE_INITIAL_SIZE / 3.0,
+                            right: player.snake.nodes[k].x + SNAKE_INITIAL_SIZE / 3.0,
+                            bottom: player.snake.nodes[k].y + SNAKE_INITIAL_SIZE / 3.0,
+                        };
+
+                        if rect_intersect(&player_i_node, &player_j_head) {
+                            deaths.push(DeathRecord::new(
+                                other_player,
+                                Some(player.id),
+                                DeathCause::Snake,
+                            ));
+                            break;
+                        }
+                    }
+                }
+            }
+
+            if in_lobby {
+                deaths.clear();
+            }
+
+            let mut msg_dead_players = String::new();
+            for death in &deaths {
+                if let Some(victim) = players_lock.get_mut(&death.victim_id) {
+                    // Generate baits from dead snake
+                    for bait in generate_mass_bait(&victim.snake) {
+                        let bt_c = bait.clone();
+                        new_bait_arr.push(bait);
+                        cur_bait.push(bt_c);
+                    }
+
+                    // Notify player about death
+                    victim.queue("", format!("{}8", COMM_START_NEW_MESS), None);
+                }
+
+                let killer_score = match death.killer_id {
+                    Some(killer_id) => players_lock
+                        .get_mut(&killer_id)
+                        .map(|killer| {
+                            killer.credit_kill(death.victim_length);
+                            killer.score
+                        })
+                        .unwrap_or(0),
+                    None => 0,
+                };
+
+                msg_dead_players.push_str(&format!(
+                    "{}{}{}",
+                    COMM_START_NEW_MESS, COMM_DEAD_ENEMY, death.victim_id
+                ));
+                msg_dead_players.push_str(&kill_feed_message(death, killer_score));
+            }
+
+            // Inform all players about dead players and who killed them
+            if !msg_dead_players.is_empty() {
+                broadcast(&msg_dead_players, &mut players_lock);
+            }
+
+            // Keep dead players connected so they can respawn, dead bots are simply dropped
+            for death in deaths {
+                if let Some(player) = players_lock.get_mut(&death.victim_id) {
+                    if let Some(round) = &mut round {
+                        round.eliminate(player);
+                    }
+                    player.die(death);
+                }
+            }
+            players_lock.retain(|_, p| !p.is_bot() || p.is_playing());
+
+            // Pull baits near a head towards it, telling clients where they moved from and to
+            let heads: Vec<(f64, f64, f64)> = players_lock
+                .values()
+                .filter(|p| p.is_playing())
+                .map(|p| {
+                    let magnet = p.snake.effect(PowerUp::Magnet).map_or(0.0, |e| e.strength);
+                    (
+                        p.snake.nodes[0].x,
+                        p.snake.nodes[0].y,
+                        config.bait.attraction_radius.max(magnet),
+                    )
+                })
+                .collect();
+            let mut moved_baits = Vec::new();
+            for bait in cur_bait.iter_mut() {
+                let (old_x, old_y) = (bait.x, bait.y);
+                if bait.attract(&heads, config.bait.attraction_speed) {
+                    let event = format!("{},{},{},{}", old_x, old_y, bait.x, bait.y);
+                    moved_baits.push((old_x, old_y, event));
+                }
+            }
+            queue_in_view(COMM_MOVE_BAITS, &moved_baits, &mut players_lock);
+
+            // Check if a player eats a bait
+            let bt_lk = cur_bait.clone();
+
+            let mut deleted_baits = Vec::new();
+            let mut cur_remove = Vec::new();
+            // let mut new_remove = Vec::new();
+            let mut grown_players: Vec<(u128, f64, f64, String)> = Vec::new();
+
+            for player in players_lock.values_mut() {
+                if !player.is_playing() {
+                    continue;
+                }
+                let plr_id = player.id;
+                let player_i_head = Rect {
+                    top: player.snake.nodes[0].y - SNAKE_INITIAL_SIZE / 2.0,
+                    left: player.snake.nodes[0].x - SNAKE_INITIAL_SIZE / 2.0,
+                    right: player.snake.nodes[0].x + SNAKE_INITIAL_SIZE / 2.0,
+                    bottom: player.snake.nodes[0].y + SNAKE_INITIAL_SIZE / 2.0,
+                };
+                let bk = bt_lk.iter().enumerate();
+                for (idx, bait_tmp) in bk {
+                    let bait_rect = Rect {
+                        top: bait_tmp.y - bait_tmp.size / 2.0,
+                        left: bait_tmp.x - bait_tmp.size / 2.0,
+                        right: bait_tmp.x + bait_tmp.size / 2.0,
+                        bottom: bait_tmp.y + bait_tmp.size / 2.0,
+                    };
+
+                    if rect_intersect(&player_i_head, &bait_rect) {
+                        // Grow the snake
+                        player.grow_player_snake();
+                        let mut msg_grown = format!("{}62,{}", COMM_START_NEW_MESS, plr_id);
+
+                        if let BaitKind::PowerUp(power_up) = bait_tmp.kind {
+                            let ticks = config.powerup.duration_ticks(power_up);
+                            player.snake.add_effect(
+                                power_up,
+                                ticks,
+                                config.powerup.strength(power_up),
+                            );
+                            msg_grown.push_str(&format!(
+                                "{}{}{},{},{}",
+                                COMM_START_NEW_MESS,
+                                COMM_POWER_UP,
+                                plr_id,
+                                power_up.code(),
+                                ticks
+                            ));
+                        }
+                        let (head_x, head_y) = (player.snake.nodes[0].x, player.snake.nodes[0].y);
+                        grown_players.push((plr_id, head_x, head_y, msg_grown));
+                        // cur_bait.remove(idx);
+                        cur_remove.push(idx);
+                        // bait::destroy(j);
+                        deleted_baits.push(bait_tmp);
+                    }
+                }
+            }
+            cur_remove.dedup();
+
+            for i in cur_remove.iter().rev() {
+                cur_bait.remove(*i);
+            }
+            // Instantly clear to avoid multiple collision
+            let new_baits: Vec<(f64, f64, String)> = new_bait_arr
+                .drain(..)
+                .map(|bait| (bait.x, bait.y, new_bait_message(&bait)))
+                .collect();
+            queue_in_view("", &new_baits, &mut players_lock);
+
+            // Inform players about deleted baits
+            let deleted_baits: Vec<(f64, f64, String)> = deleted_baits
+                .iter()
+                .map(|bait| {
+                    let msg = format!("{}4,{},{}", COMM_START_NEW_MESS, bait.x, bait.y);
+                    (bait.x, bait.y, msg)
+                })
+                .collect();
+            queue_in_view("", &deleted_baits, &mut players_lock);
+
+            // Growth and power-ups go to the clients that know the snake
+            for (snake_id, x, y, msg) in &grown_players {
+                for player in players_lock.values_mut() {
+                    if player.id == *snake_id || player.known_snakes.contains(snake_id) {
+                        player.queue("", msg.clone(), Some((*x, *y)));
+                    }
+                }
+            }
+
+            let snapshot = snapshot_message(tick);
+
+            // Dead and spectating players look through their camera instead of their snake
+            let mut cameras: HashMap<u128, (Camera, f64, f64)> = HashMap::new();
+            for player in players_lock.values() {
+                if !player.is_playing() {
+                    cameras.insert(
+                        player.id,
+                        resolve_camera(player, &players_lock, &config.map),
+                    );
+                }
+            }
+            for (id, (camera, _, _)) in &cameras {
+                if let Some(player) = players_lock.get_mut(id) {
+                    player.camera = *camera;
+                }
+            }
+
+            self.send_views(
+                &mut players_lock,
+                &cur_bait,
+                &cameras,
+                &config,
+                tick,
+                &snapshot,
+            )
+            .await;
+
+            // Clean up inactive players (UDP connection management)
+            let inactive_players = self.get_inactive_players(5, &players_lock); // 5 seconds timeout
+            let mut msg_disconnected = String::new();
+            for id in &inactive_players {
+                println!("Player {} disconnected due to inactivity", id);
+                let Some(player) = players_lock.remove(id) else {
+                    continue;
+                };
+                if let Some(round) = &mut round {
+                    round.eliminate(&player);
+                }
+                if player.is_playing() {
+                    let death = DeathRecord::new(&player, None, DeathCause::Disconnect);
+                    msg_disconnected.push_str(&format!(
+                        "{}{}{}",
+                        COMM_START_NEW_MESS, COMM_DEAD_ENEMY, player.id
+                    ));
+                    msg_disconnected.push_str(&kill_feed_message(&death, 0));
+                }
+            }
+
+            // Notify remaining players
+            if !msg_disconnected.is_empty() {
+                broadcast(&msg_disconnected, &mut players_lock);
+            }
+
+            let mut loss_players: Vec<SocketAddr> = Vec::new();
+            for plr in players_lock.values() {
+                let Some(plr_addr) = plr.addr else {
+                    continue;
+                };
+                let Some((server_send, _, server_recv, _)) = self.socket.stats_for(&plr_addr).await
+                else {
+                    continue;
+                };
+                // println!("{} --- Send:{},Recv:{}", plr.addr, server_send, server_recv,);
+                // Found Packet loss
+                if (((plr.sent_pkt as f64 / server_recv as f64)
+                    + (server_send as f64 / plr.recv_pkt as f64))
+                    / 2.0
+                    < 0.98)
+                    && plr.sent_pkt > 200
+                {
+                    loss_players.push(plr_addr);
+                }
+            }
+            let i: Vec<SocketAddr> = loss_players.clone();
+            let baits = cur_bait;
+            for id in i {
+                println!("Player {} is in high packet loss env, sync status:", id);
+
+                println!("Sync all current baits");
+                let mut all_bait_arr = String::new();
+                for bait in baits.iter() {
+                    all_bait_arr.push_str(&format!(
+                        "{}{}{},{},{},{},{}",
+                        COMM_START_NEW_MESS,
+                        COMM_SYNC_BAITS,
+                        bait.x,
+                        bait.y,
+                        bait.size,
+                        bait.color,
+                        bait.kind.code()
+                    ));
+                }
+                // if !all_bait_arr.is_empty() {
+                //     if let Err(e) = self.socket.send_to(all_bait_arr.as_bytes(), id).await {
+                //         eprintln!("Failed to send welcome to {}: {}", id, e);
+                //     }
+                // }
+            }
+        }
+    }
+
+    // Find players that haven't been seen in a while (UDP connection management)
+    fn get_inactive_players(
+        &self,
+        timeout_secs: u64,
+        players_lock: &HashMap<u128, Player>,
+    ) -> Vec<u128> {
+        players_lock
+            .values()
+            .filter(|player| !player.is_bot())
+            .filter(|player| player.last_seen.elapsed().as_secs() > timeout_secs)
+            .map(|player| player.id)
+            .collect()
+    }
+
+    // Number of teams players are split into, in the teams mode only
+    fn team_count(&self, config: &GameConfig) -> Option<usize> {
+        (self.mode() == GameMode::Teams).then_some(config.team.count)
+    }
+
+    /// Send every client what changed around its snake or camera this tick.
+    /// Snakes and baits that came into its view are sent in full, and it is
+    /// told which ones left it. Snakes further from the center of the view are
+    /// updated with fewer nodes and, far out, less often.
+    ///
+    /// Apart from the client's own snake or camera, everything it is sent
+    /// competes for its byte budget: snake updates and queued messages collect
+    /// priority each tick, more the closer to the center they are, and what
+    /// doesn't fit waits for a later tick. It all goes out in datagrams of at
+    /// most NET_DATAGRAM_SIZE bytes.
+    async fn send_views(
+        &self,
+        players_lock: &mut HashMap<u128, Player>,
+        baits: &[Bait],
+        cameras: &HashMap<u128, (Camera, f64, f64)>,
+        config: &GameConfig,
+        tick: u64,
+        snapshot: &str,
+    ) {
+        let (lod, net) = (&config.lod, &config.net);
+        let gain = |distance: f64| 1.0 / (1.0 + distance / NET_PRIORITY_DISTANCE);
+        // Queues are taken out so the other players can still be read meanwhile
+        let mut queues: HashMap<u128, Vec<Pending>> = players_lock
+            .values_mut()
+            .map(|p| (p.id, std::mem::take(&mut p.pending)))
+            .collect();
+        let mut views = Vec::new();
+        for player in players_lock.values() {
+            let mut queue = queues.remove(&player.id).unwrap_or_default();
+            let Some(addr) = player.addr else {
+                continue;
+            };
+            let mut outgoing = Vec::new();
+            let (center_x, center_y) = match cameras.get(&player.id) {
+                Some((_, camera_x, camera_y)) => {
+                    outgoing.push(Outgoing::Current(format!(
+                        "{}{}{:.4},{:.4}",
+                        COMM_START_NEW_MESS, COMM_CAMERA, camera_x, camera_y
+                    )));
+                    (*camera_x, *camera_y)
+                }
+                None => {
+                    outgoing.push(Outgoing::Current(own_update_message(player)));
+                    (player.snake.nodes[0].x, player.snake.nodes[0].y)
+                }
+            };
+            let view = client_view(player, center_x, center_y);
+
+            // Baits that came into view, and the ones the client can forget
+            for bait in baits {
+                let inside = view.contains(bait.x, bait.y);
+                let was_inside = player.view.is_some_and(|v| v.contains(bait.x, bait.y));
+                if inside == was_inside {
+                    continue;
+                }
+                let (code, text) = if inside {
+                    ("", new_bait_message(bait))
+                } else {
+                    (COMM_LEAVE_BAITS, format!("{},{}", bait.x, bait.y))
+                };
+                queue.push(Pending {
+                    code,
+                    text,
+                    at: Some((bait.x, bait.y)),
+                    priority: 0.0,
+                });
+            }
+
+            // Snakes that left the view. One that comes back before the client
+            // was told is simply still known.
+            let in_view = |other: &Player| {
+                other.id != player.id
+                    && other.is_playing()
+                    && other
+                        .snake
+                        .nodes
+                        .iter()
+                        .any(|node| view.contains(node.x, node.y))
+            };
+            // Snakes that died or left were announced to everyone already
+            for id in &player.known_snakes {
+                let Some(other) = players_lock.get(id).filter(|p| p.is_playing()) else {
+                    continue;
+                };
+                let leave = leave_enemy_message(*id);
+                let queued = queue.iter().position(|p| p.text == leave);
+                match queued {
+                    Some(index) if in_view(other) => {
+                        queue.remove(index);
+                    }
+                    None if !in_view(other) => {
+                        let head = &other.snake.nodes[0];
+                        queue.push(Pending {
+                            code: "",
+                            text: leave,
+                            at: Some((head.x, head.y)),
+                            priority: 0.0,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut candidates: Vec<(f64, Outgoing)> = Vec::new();
+            for mut pending in queue.drain(..) {
+                pending.priority += pending
+                    .at
+                    .map_or(1.0, |(x, y)| gain((x - center_x).hypot(y - center_y)));
+                candidates.push((pending.priority, Outgoing::Queued(pending)));
+            }
+            let mut priorities = HashMap::new();
+            for other_player in players_lock.values().filter(|p| in_view(p)) {
+                let distance = other_player
+                    .snake
+                    .nodes
+                    .iter()
+                    .map(|node| (node.x - center_x).hypot(node.y - center_y))
+                    .fold(f64::MAX, f64::min);
+                let priority =
+                    player.priorities.get(&other_player.id).unwrap_or(&0.0) + gain(distance);
+                priorities.insert(other_player.id, priority);
+
+                let update = if !player.known_snakes.contains(&other_player.id) {
+                    format!(
+                        "{}{}{},{},{}{}",
+                        COMM_START_NEW_MESS,
+                        COMM_NEW_ENEMY,
+                        other_player.id,
+                        other_player.name,
+                        format_nodes(&other_player.snake),
+                        appearance_message(other_player)
+                    )
+                } else if distance >= lod.far_distance {
+                    // Spread the far updates of different snakes over the interval
+                    if !(tick + other_player.id as u64).is_multiple_of(lod.far_interval) {
+                        continue;
+                    }
+                    lod_update_message(other_player, lod.far_step)
+                } else if distance >= lod.mid_distance {
+                    lod_update_message(other_player, lod.mid_step)
+                } else {
+                    format!(
+                        "{}{}{},{}",
+                        COMM_START_NEW_MESS,
+                        COMM_UPDATE_ENEMY,
+                        other_player.id,
+                        format_nodes(&other_player.snake)
+                    )
+                };
+                candidates.push((priority, Outgoing::Snake(other_player.id, update)));
+            }
+            // Stable, so of two queued messages with the same priority the older goes first
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            // Room for the snapshot header of every datagram the budget can fill,
+            // plus one for datagrams that end short of full
+            let payload = NET_DATAGRAM_SIZE - snapshot.len();
+            let headers = snapshot.len() * (net.budget.div_ceil(payload) + 1);
+            let used: usize = outgoing.iter().map(|item| item.message().len()).sum();
+            let mut budget = net.budget.saturating_sub(headers + used);
+            // Once a queued message has to wait, the ones after it wait as well, so
+            // the events of one bait reach the client in order
+            let mut queue_waits = false;
+            for (i, (_, item)) in candidates.into_iter().enumerate() {
+                let len = item.message().len();
+                let queued = matches!(item, Outgoing::Queued(_));
+                // The most important one goes out even when it is over budget
+                if (len > budget && i > 0) || (queued && queue_waits) {
+                    if let Outgoing::Queued(pending) = item {
+                        queue_waits = true;
+                        queue.push(pending);
+                    }
+                    continue;
+                }
+                budget = budget.saturating_sub(len);
+                outgoing.push(item);
+            }
+
+            // Every datagram carries the snapshot header. What the client learns
+            // from one only counts once it was actually sent.
+            let mut sent = vec![false; outgoing.len()];
+            for (run, datagram) in pack_datagrams(&outgoing, snapshot) {
+                match self.socket.send_to(datagram.as_bytes(), addr).await {
+                    Ok(_) => sent[run].fill(true),
+                    Err(e) => eprintln!("Failed to send view to {}: {}", addr, e),
+                }
+            }
+            // Snakes stay known once sent in full, even on ticks they get no update,
+            // and until the client is told they left
+            let mut known: HashSet<u128> = player
+                .known_snakes
+                .iter()
+                .filter(|id| priorities.contains_key(id))
+                .copied()
+                .collect();
+            for (item, sent) in outgoing.into_iter().zip(sent) {
+                match item {
+                    Outgoing::Snake(id, _) if sent => {
+                        known.insert(id);
+                        priorities.insert(id, 0.0);
+                    }
+                    Outgoing::Queued(pending) if !sent => queue.push(pending),
+                    _ => {}
+                }
+            }
+            for id in &player.known_snakes {
+                let leave = leave_enemy_message(*id);
+                if queue.iter().any(|p| p.text == leave) {
+                    known.insert(*id);
+                }
+            }
+            views.push((player.id, view, known, priorities, queue));
+        }
+
+        for (id, view, known, priorities, queue) in views {
+            if let Some(player) = players_lock.get_mut(&id) {
+                player.view = Some(view);
+                player.known_snakes = known;
+                player.priorities = priorities;
+                player.pending = queue;
+            }
+        }
+    }
+
+    /// Add or remove one bot per tick so that humans plus bots reach bot.target
+    async fn balance_bots(&self, players_lock: &mut HashMap<u128, Player>, config: &GameConfig) {
+        let humans = players_lock
+            .values()
+            .filter(|p| !p.is_bot() && p.state != PlayerState::Spectating)
+            .count();
+        let bots: Vec<u128> = players_lock
+            .values()
+            .filter(|p| p.is_bot())
+            .map(|p| p.id)
+            .collect();
+        let wanted = config.bot.target.saturating_sub(humans);
+
+        if bots.len() < wanted {
+            let bot_id = new_player_id(players_lock);
+            let team = self
+                .team_count(config)
+                .map(|count| smallest_team(players_lock, count));
+            let bot_snake = new_safe_snake(players_lock, config, team);
+            let mut bot = Player::new_bot(bot_id, format!("Bot {}", bot_id % 1000), bot_snake);
+            bot.team = team;
+            // Clients see it once it comes into their view
+            players_lock.insert(bot_id, bot);
+        } else if bots.len() > wanted {
+            players_lock.remove(&bots[0]);
+            let msg = format!("{}{}{}", COMM_START_NEW_MESS, COMM_DEAD_ENEMY, bots[0]);
+            broadcast(&msg, players_lock);
+        }
+    }
+
+    /// Give a player or bot a new snake, keeping their id and the baits they already know
+    async fn respawn_player(
+        &self,
+        player_id: u128,
+        players_lock: &mut HashMap<u128, Player>,
+        config: &GameConfig,
+    ) {
+        let Some(team) = players_lock.get(&player_id).map(|p| p.team) else {
+            return;
+        };
+        let player_snake = new_safe_snake(players_lock, config, team);
+        let nodes = format_nodes(&player_snake);
+
+        let Some(player) = players_lock.get_mut(&player_id) else {
+            return;
+        };
+        player.respawn(player_snake);
+        println!("Player {} respawned", player.id);
+
+        let addr = player.addr;
+        let appearance = appearance_message(player);
+        let msg = format!(
+            "{}{}{}{}",
+            COMM_START_NEW_MESS, COMM_NEW_SNAKE, nodes, appearance
+        );
+        if let Some(addr) = addr
+            && let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await
+        {
+            eprintln!("Failed to send respawn to {}: {}", addr, e);
+        }
+        // Others are sent the new snake in full once it is in their view
+        for other in players_lock.values_mut() {
+            other.known_snakes.remove(&player_id);
+        }
+    }
+
+    /// Handle a client that only wants to watch. Spectators get what is around
+    /// their camera but no snake, and are not announced to the other players. A player joining
+    /// during a round watches the same way until the next one starts.
+    async fn create_spectator(
+        &self,
+        addr: SocketAddr,
+        data: &[u8],
+        mut players_lock: MutexGuard<'_, HashMap<u128, Player>>,
+        map: &MapConfig,
+        waiting_for_round: bool,
+    ) {
+        let message = String::from_utf8_lossy(data);
+        let splitted: Vec<&str> = message.split(',').collect();
+
+        let player_id = new_player_id(&players_lock);
+        let name = splitted.get(1).unwrap_or(&"").to_string();
+        let placeholder_snake = Snake::new(rand::random_range(0..SNAKE_SKIN_COLOR_RANGE), map);
+
+        let mut spectator = Player::new(player_id, name, placeholder_snake, addr);
+        let (center_x, center_y) = map.center();
+        spectator.spectate(Camera::Area {
+            x: center_x,
+            y: center_y,
+        });
+        if waiting_for_round {
+            spectator.state = PlayerState::Dead;
+        }
+
+        // Snakes and baits around the camera follow with the next update
+        players_lock.insert(player_id, spectator);
+    }
+
+    /// Handle creation of a new player
+    async fn create_player(
+        &self,
+        addr: SocketAddr,
+        data: &[u8],
+        mut players_lock: MutexGuard<'_, HashMap<u128, Player>>,
+        config: &GameConfig,
+    ) {
+        let message = String::from_utf8_lossy(data);
+        let splitted: Vec<&str> = message.split(',').collect();
+
+        let player_id = new_player_id(&players_lock);
+        println!("New player created: {}", player_id);
+
+        // Teams mode puts the player in the smallest team
+        let team = self
+            .team_count(config)
+            .map(|count| smallest_team(&players_lock, count));
+
+        // Create a new snake away from the others
+        let player_snake = new_safe_snake(&players_lock, config, team);
+
+        // Create the player
+        let name = splitted.get(1).unwrap_or(&"").to_string();
+
+        let mut new_player = Player::new(player_id, name, player_snake.clone(), addr);
+        new_player.team = team;
+        let appearance = appearance_message(&new_player);
+
+        // Send first snake back to the client
+        let mut msg = format!("{}1,", COMM_START_NEW_MESS);
+        for (i, node) in player_snake.nodes.iter().enumerate() {
+            msg.push_str(&format!("{:.4},{:.4}", node.x, node.y));
+            if i < player_snake.nodes.len() - 1 {
+                msg.push(',');
+            }
+        }
+
+        msg.push_str(&appearance);
+        if let Err(e) = self.socket.send_to(msg.as_bytes(), addr).await {
+            eprintln!("Failed to send new snake to {}: {}", addr, e);
+        }
+
+        // Player sends their name to all other players
+
+        // Update the player's name
+
+        // Notify all other players
+        let msg_enemy_name = format!(
+            "{}{}{},{}",
+            COMM_START_NEW_MESS, COMM_ENEMY_NAME, new_player.id, new_player.name
+        );
+
+        broadcast(&msg_enemy_name, &mut players_lock);
+
+        // Snakes and baits around it follow with the next update, and the other
+        // players are sent the new snake once it is in their view
+        println!("Total player(s): {}", players_lock.len());
+        players_lock.insert(player_id, new_player);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(code: &'static str, text: &str) -> Outgoing {
+        Outgoing::Queued(Pending {
+            code,
+            text: text.to_string(),
+            at: None,
+            priority: 0.0,
+        })
+    }
+
+    #[test]
+    fn pack_joins_neighbours_with_the_same_code() {
+        let items = [
+            queued(COMM_NEW_BAIT, "1,2"),
+            queued(COMM_NEW_BAIT, "3,4"),
+            Outgoing::Current("$2,x".to_string()),
+            queued(COMM_NEW_BAIT, "5,6"),
+            queued("", "$9,name"),
+            queued("", "$9,other"),
+        ];
+        let datagrams = pack_datagrams(&items, "$36,1");
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].0, 0..6);
+        assert_eq!(datagrams[0].1, "$36,1$3,1,2,3,4$2,x$3,5,6$9,name$9,other");
+    }
+
+    #[test]
+    fn pack_splits_at_the_datagram_size() {
+        let text = format!("$2,{}", "x".repeat(97));
+        let mut items: Vec<Outgoing> = (0..50).map(|_| Outgoing::Current(text.clone())).collect();
+        items.extend((0..500).map(|i| queued(COMM_NEW_BAIT, &format!("{},{}", i, i))));
+        let datagrams = pack_datagrams(&items, "$36,1");
+
+        let mut next = 0;
+        for (range, datagram) in &datagrams {
+            assert!(datagram.len() <= NET_DATAGRAM_SIZE);
+            assert_eq!(range.start, next);
+            // A datagram picks up a joined run with the code again
+            assert!(datagram[5..].starts_with('$'));
+            next = range.end;
+        }
+        assert_eq!(next, items.len());
+        assert!(datagrams.len() > 5);
+    }
+
+    #[test]
+    fn pack_gives_a_long_message_its_own_datagram() {
+        let long = format!("$2,{}", "x".repeat(NET_DATAGRAM_SIZE));
+        let items = [
+            Outgoing::Current("$2,a".to_string()),
+            Outgoing::Current(long.clone()),
+            Outgoing::Current("$2,b".to_string()),
+        ];
+        let datagrams = pack_datagrams(&items, "");
+        let ranges: Vec<_> = datagrams.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, vec![0..1, 1..2, 2..3]);
+        assert_eq!(datagrams[1].1, long);
+    }
+}
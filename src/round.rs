@@ -0,0 +1,201 @@
+// Timed rounds: a lobby countdown, then a round whose arena shrinks until the
+// time is up or one snake is left
+
+use crate::config::{MapConfig, RoundConfig};
+use crate::constants::*;
+use crate::player::Player;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RoundPhase {
+    Lobby,
+    Playing,
+}
+
+impl RoundPhase {
+    pub fn code(&self) -> i32 {
+        match self {
+            RoundPhase::Lobby => 0,
+            RoundPhase::Playing => 1,
+        }
+    }
+}
+
+/// What happened to the round this tick
+pub enum RoundEvent {
+    Started,
+    Ended,
+}
+
+pub struct Round {
+    pub phase: RoundPhase,
+    config: RoundConfig,
+    ticks_left: i32,
+    // Everyone who got a snake when the round started
+    pub participants: Vec<u128>,
+    // Participants in the order they died or left, with their kills and score
+    pub eliminated: Vec<(u128, u32, u32)>,
+}
+
+fn seconds_to_ticks(seconds: f64) -> i32 {
+    (seconds * 1000.0 / GAME_LOOP_DELAY as f64) as i32
+}
+
+impl Round {
+    pub fn new(config: RoundConfig) -> Round {
+        Round {
+            phase: RoundPhase::Lobby,
+            ticks_left: seconds_to_ticks(config.lobby_seconds),
+            config,
+            participants: Vec::new(),
+            eliminated: Vec::new(),
+        }
+    }
+
+    /// Advance one tick. The lobby only counts down while enough snakes are
+    /// waiting, and a round ends early once at most one participant is alive.
+    pub fn tick(&mut self, waiting: usize, alive: usize) -> Option<RoundEvent> {
+        match self.phase {
+            RoundPhase::Lobby => {
+                if waiting < self.config.min_players {
+                    self.ticks_left = seconds_to_ticks(self.config.lobby_seconds);
+                    return None;
+                }
+                self.ticks_left -= 1;
+                if self.ticks_left > 0 {
+                    return None;
+                }
+                self.phase = RoundPhase::Playing;
+                self.ticks_left = seconds_to_ticks(self.config.duration_seconds);
+                self.participants.clear();
+                self.eliminated.clear();
+                Some(RoundEvent::Started)
+            }
+            RoundPhase::Playing => {
+                self.ticks_left -= 1;
+                if self.ticks_left > 0 && alive > 1 {
+                    return None;
+                }
+                self.phase = RoundPhase::Lobby;
+                self.ticks_left = seconds_to_ticks(self.config.lobby_seconds);
+                Some(RoundEvent::Ended)
+            }
+        }
+    }
+
+    pub fn seconds_left(&self) -> i32 {
+        self.ticks_left * GAME_LOOP_DELAY / 1000
+    }
+
+    pub fn eliminate(&mut self, player: &Player) {
+        if self.phase == RoundPhase::Playing
+            && self.participants.contains(&player.id)
+            && !self.eliminated.iter().any(|(id, _, _)| *id == player.id)
+        {
+            self.eliminated
+                .push((player.id, player.kills, player.score));
+        }
+    }
+
+    /// The area snakes have to stay in. It is the whole map until shrink_start
+    /// of the round has passed, then shrinks towards the center down to
+    /// final_size of the map when time runs out.
+    pub fn arena(&self, map: &MapConfig) -> MapConfig {
+        if self.phase != RoundPhase::Playing {
+            return *map;
+        }
+        let total = seconds_to_ticks(self.config.duration_seconds).max(1) as f64;
+        let elapsed = 1.0 - self.ticks_left as f64 / total;
+        let shrink_range = (1.0 - self.config.shrink_start).max(f64::EPSILON);
+        let t = ((elapsed - self.config.shrink_start) / shrink_range).clamp(0.0, 1.0);
+        map.scaled(1.0 - (1.0 - self.config.final_size) * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 10 tick lobby and a 100 tick round
+    fn round() -> Round {
+        Round::new(RoundConfig {
+            lobby_seconds: 0.8,
+            duration_seconds: 8.0,
+            min_players: 2,
+            shrink_start: 0.5,
+            final_size: 0.2,
+        })
+    }
+
+    fn start(round: &mut Round) {
+        for _ in 0..9 {
+            assert!(round.tick(2, 0).is_none());
+        }
+        assert!(matches!(round.tick(2, 0), Some(RoundEvent::Started)));
+    }
+
+    #[test]
+    fn lobby_waits_for_enough_players() {
+        let mut round = round();
+        for _ in 0..100 {
+            assert!(round.tick(1, 0).is_none());
+        }
+        assert_eq!(round.phase, RoundPhase::Lobby);
+        start(&mut round);
+        assert_eq!(round.phase, RoundPhase::Playing);
+    }
+
+    #[test]
+    fn lobby_restarts_its_countdown_when_players_leave() {
+        let mut round = round();
+        for _ in 0..9 {
+            round.tick(2, 0);
+        }
+        round.tick(1, 0);
+        start(&mut round);
+    }
+
+    #[test]
+    fn round_ends_when_time_runs_out() {
+        let mut round = round();
+        start(&mut round);
+        for _ in 0..99 {
+            assert!(round.tick(0, 2).is_none());
+        }
+        assert!(matches!(round.tick(0, 2), Some(RoundEvent::Ended)));
+        assert_eq!(round.phase, RoundPhase::Lobby);
+    }
+
+    #[test]
+    fn round_ends_when_one_snake_is_left() {
+        let mut round = round();
+        start(&mut round);
+        assert!(round.tick(0, 3).is_none());
+        assert!(matches!(round.tick(0, 1), Some(RoundEvent::Ended)));
+    }
+
+    #[test]
+    fn arena_shrinks_after_shrink_start() {
+        let map = MapConfig {
+            left: 0.0,
+            top: 0.0,
+            right: 1000.0,
+            bottom: 1000.0,
+        };
+        let mut round = round();
+        assert_eq!(round.arena(&map), map);
+
+        start(&mut round);
+        for _ in 0..50 {
+            round.tick(0, 2);
+        }
+        assert_eq!(round.arena(&map), map);
+
+        // Halfway through the shrinking part of the round
+        for _ in 0..25 {
+            round.tick(0, 2);
+        }
+        let arena = round.arena(&map);
+        assert!((arena.width() - 600.0).abs() < 1e-9);
+        assert_eq!(arena.center(), map.center());
+    }
+}